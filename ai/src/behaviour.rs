@@ -0,0 +1,231 @@
+extern crate ds;
+
+use std::collections::BTreeMap;
+
+// how far ahead of its starting point a patrolling soldier walks (meters)
+const PATROL_DISTANCE: f64 = 100.0;
+// a waypoint closer than this counts as reached (meters)
+const WAYPOINT_RADIUS: f64 = 2.0;
+// soldiers engaging an enemy stop to fire once this close to it (meters)
+const FIRING_DISTANCE: f64 = 150.0;
+// an engaging soldier is sent towards the enemy again once it has moved this far (meters)
+const REPATH_DISTANCE: f64 = 10.0;
+
+// decides what a single soldier does
+pub trait Behaviour {
+	// called when the soldier comes under our control
+	fn start(&mut self, sid: ds::SoldierID, me: &ds::FullSoldierInfo) -> Vec<ds::GameMsg>;
+	// called with what the soldier sensed, whenever it changes
+	fn sensor_update(&mut self, sid: ds::SoldierID, me: &ds::FullSoldierInfo,
+			 upd: &ds::SensorUpdate) -> Vec<ds::GameMsg>;
+}
+
+pub const BEHAVIOURS: &[&str] = &["hold", "engage", "advance", "patrol"];
+
+// advancing soldiers walk to the given objective
+pub fn create(name: &str, objective: ds::Position) -> Option<Box<dyn Behaviour>> {
+	match name {
+		"hold"    => Some(Box::new(Hold::new())),
+		"engage"  => Some(Box::new(Engage::new())),
+		"advance" => Some(Box::new(Advance::new(objective))),
+		"patrol"  => Some(Box::new(Patrol::new())),
+		_         => None
+	}
+}
+
+// what a soldier currently sees, and who it is firing at
+pub struct Contacts {
+	seen: BTreeMap<i32, ds::SeenSoldierInfo>,
+	target: Option<i32>,
+}
+
+impl Contacts {
+	pub fn new() -> Contacts {
+		Contacts {
+			seen: BTreeMap::new(),
+			target: None,
+		}
+	}
+
+	pub fn update(&mut self, upd: &ds::SensorUpdate) {
+		for (ds::SoldierID(s), info) in upd.insense.iter() {
+			self.seen.insert(*s, info.clone());
+		}
+		for ds::SoldierID(s) in upd.outsense.iter() {
+			self.seen.remove(s);
+		}
+	}
+
+	pub fn nearest_enemy(&self, me: &ds::SeenSoldierInfo) -> Option<(ds::SoldierID, ds::SeenSoldierInfo)> {
+		self.seen.iter()
+			.filter(|(_, info)| info.alive && info.side != me.side)
+			.min_by(|(_, a), (_, b)| me.position.dist(&a.position)
+				.partial_cmp(&me.position.dist(&b.position)).unwrap())
+			.map(|(s, info)| (ds::SoldierID(*s), info.clone()))
+	}
+
+	// engages the nearest enemy if it isn't the target already
+	pub fn retarget(&mut self, sid: ds::SoldierID, me: &ds::SeenSoldierInfo) -> Option<ds::GameMsg> {
+		let enemy = self.nearest_enemy(me).map(|(ds::SoldierID(e), _)| e);
+		if enemy == self.target {
+			return None;
+		}
+		self.target = enemy;
+		enemy.map(|e| ds::GameMsg::Engage(sid, ds::SoldierID(e)))
+	}
+}
+
+// stays put and fires at the nearest enemy in sight
+pub struct Hold {
+	contacts: Contacts,
+}
+
+impl Hold {
+	pub fn new() -> Hold {
+		Hold {
+			contacts: Contacts::new(),
+		}
+	}
+}
+
+impl Behaviour for Hold {
+	fn start(&mut self, _sid: ds::SoldierID, _me: &ds::FullSoldierInfo) -> Vec<ds::GameMsg> {
+		vec![]
+	}
+
+	fn sensor_update(&mut self, sid: ds::SoldierID, me: &ds::FullSoldierInfo,
+			 upd: &ds::SensorUpdate) -> Vec<ds::GameMsg> {
+		self.contacts.update(upd);
+		self.contacts.retarget(sid, &me.external).into_iter().collect()
+	}
+}
+
+// closes in on the nearest enemy in sight and fires at it
+pub struct Engage {
+	contacts: Contacts,
+	// where the soldier was last sent
+	destination: Option<ds::Position>,
+}
+
+impl Engage {
+	pub fn new() -> Engage {
+		Engage {
+			contacts: Contacts::new(),
+			destination: None,
+		}
+	}
+}
+
+impl Behaviour for Engage {
+	fn start(&mut self, _sid: ds::SoldierID, _me: &ds::FullSoldierInfo) -> Vec<ds::GameMsg> {
+		vec![]
+	}
+
+	fn sensor_update(&mut self, sid: ds::SoldierID, me: &ds::FullSoldierInfo,
+			 upd: &ds::SensorUpdate) -> Vec<ds::GameMsg> {
+		self.contacts.update(upd);
+		let mut ret: Vec<ds::GameMsg> = self.contacts.retarget(sid, &me.external).into_iter().collect();
+		let pos = me.external.position;
+		let dest = match self.contacts.nearest_enemy(&me.external) {
+			Some((_, enemy)) if pos.dist(&enemy.position) > FIRING_DISTANCE => enemy.position,
+			// close enough or nothing to chase, stand still
+			_ => pos,
+		};
+		let repath = match self.destination {
+			Some(d) => d.dist(&dest) > REPATH_DISTANCE,
+			None => dest != pos,
+		};
+		if repath {
+			self.destination = Some(dest);
+			ret.push(ds::GameMsg::MoveTo(sid, dest));
+		}
+		ret
+	}
+}
+
+// walks to the objective, stopping to fire whenever an enemy is in sight
+pub struct Advance {
+	contacts: Contacts,
+	objective: ds::Position,
+	halted: bool,
+}
+
+impl Advance {
+	pub fn new(objective: ds::Position) -> Advance {
+		Advance {
+			contacts: Contacts::new(),
+			objective: objective,
+			halted: false,
+		}
+	}
+}
+
+impl Behaviour for Advance {
+	fn start(&mut self, sid: ds::SoldierID, _me: &ds::FullSoldierInfo) -> Vec<ds::GameMsg> {
+		vec![ds::GameMsg::MoveTo(sid, self.objective)]
+	}
+
+	fn sensor_update(&mut self, sid: ds::SoldierID, me: &ds::FullSoldierInfo,
+			 upd: &ds::SensorUpdate) -> Vec<ds::GameMsg> {
+		self.contacts.update(upd);
+		let mut ret: Vec<ds::GameMsg> = self.contacts.retarget(sid, &me.external).into_iter().collect();
+		let enemy = self.contacts.nearest_enemy(&me.external).is_some();
+		if enemy && !self.halted {
+			self.halted = true;
+			ret.push(ds::GameMsg::MoveTo(sid, me.external.position));
+		} else if !enemy && self.halted {
+			self.halted = false;
+			ret.push(ds::GameMsg::MoveTo(sid, self.objective));
+		}
+		ret
+	}
+}
+
+// walks back and forth between its starting point and a point ahead of
+// it, firing at enemies on the way
+pub struct Patrol {
+	contacts: Contacts,
+	route: Vec<ds::Position>,
+	next: usize,
+}
+
+impl Patrol {
+	pub fn new() -> Patrol {
+		Patrol {
+			contacts: Contacts::new(),
+			route: vec![],
+			next: 0,
+		}
+	}
+
+	pub fn with_route(route: Vec<ds::Position>) -> Patrol {
+		Patrol {
+			route: route,
+			..Patrol::new()
+		}
+	}
+}
+
+impl Behaviour for Patrol {
+	fn start(&mut self, sid: ds::SoldierID, me: &ds::FullSoldierInfo) -> Vec<ds::GameMsg> {
+		if self.route.is_empty() {
+			let pos = me.external.position;
+			let ds::Direction(dir) = me.external.direction;
+			self.route = vec![pos, ds::Position::new(pos.x + dir.cos() * PATROL_DISTANCE,
+								 pos.y + dir.sin() * PATROL_DISTANCE)];
+			self.next = 1;
+		}
+		vec![ds::GameMsg::MoveTo(sid, self.route[self.next])]
+	}
+
+	fn sensor_update(&mut self, sid: ds::SoldierID, me: &ds::FullSoldierInfo,
+			 upd: &ds::SensorUpdate) -> Vec<ds::GameMsg> {
+		self.contacts.update(upd);
+		let mut ret: Vec<ds::GameMsg> = self.contacts.retarget(sid, &me.external).into_iter().collect();
+		if !self.route.is_empty() && me.external.position.dist(&self.route[self.next]) < WAYPOINT_RADIUS {
+			self.next = (self.next + 1) % self.route.len();
+			ret.push(ds::GameMsg::MoveTo(sid, self.route[self.next]));
+		}
+		ret
+	}
+}
@@ -0,0 +1,141 @@
+extern crate ds;
+
+use std::collections::BTreeMap;
+
+use crate::behaviour::{self, Behaviour};
+
+// a soldier under our control
+struct Agent {
+	me: ds::FullSoldierInfo,
+	// None until we know where the enemy is
+	behaviour: Option<Box<dyn Behaviour>>,
+}
+
+// where the other side deploys, once we know our side and the map
+pub(crate) fn enemy_line(side: Option<ds::Side>, bounds: Option<ds::Position>) -> Option<f64> {
+	let enemy = match side {
+		Some(ds::Side::Red) => ds::Side::Blue,
+		Some(ds::Side::Blue) => ds::Side::Red,
+		None => return None
+	};
+	bounds.map(|b| ds::deployment_line(enemy, &b))
+}
+
+// plays a side based on the messages from the game
+pub trait Commander {
+	fn receive(&mut self, msg: ds::ServerMsg) -> Vec<ds::GameMsg>;
+	// whether any soldiers are under our control
+	fn has_agents(&self) -> bool;
+}
+
+// takes control of all soldiers of the side and runs a behaviour for
+// each of them independently
+pub struct Controller {
+	behaviour: String,
+	agents: BTreeMap<i32, Agent>,
+	// our side is kept from game to game, the map is sent for every game
+	side: Option<ds::Side>,
+	bounds: Option<ds::Position>,
+}
+
+impl Controller {
+	pub fn new(behaviour: &str) -> Result<Controller, String> {
+		if !behaviour::BEHAVIOURS.contains(&behaviour) {
+			return Err(format!("unknown behaviour {}, available: {}",
+					   behaviour, behaviour::BEHAVIOURS.join(", ")));
+		}
+		Ok(Controller {
+			behaviour: behaviour.to_string(),
+			agents: BTreeMap::new(),
+			side: None,
+			bounds: None,
+		})
+	}
+
+	// every soldier heads for the enemy's deployment line, soldiers we got
+	// before knowing where that is start once we do
+	fn start_agents(&mut self) -> Vec<ds::GameMsg> {
+		let front = match enemy_line(self.side, self.bounds) {
+			Some(x) => x,
+			None => return vec![]
+		};
+		let mut ret = vec![];
+		for (s, agent) in self.agents.iter_mut().filter(|(_, a)| a.behaviour.is_none()) {
+			let pos = agent.me.external.position;
+			let mut behaviour = behaviour::create(&self.behaviour, ds::Position::new(front, pos.y)).unwrap();
+			ret.extend(behaviour.start(ds::SoldierID(*s), &agent.me));
+			agent.behaviour = Some(behaviour);
+		}
+		ret
+	}
+}
+
+impl Commander for Controller {
+	fn receive(&mut self, msg: ds::ServerMsg) -> Vec<ds::GameMsg> {
+		match msg {
+			ds::ServerMsg::NewGame(soldiers) => {
+				self.agents.clear();
+				self.bounds = None;
+				soldiers.into_iter().map(|s| ds::GameMsg::TakeControl(s)).collect()
+			}
+			ds::ServerMsg::AvailableSoldiers(soldiers) => {
+				soldiers.into_iter().map(|s| ds::GameMsg::TakeControl(s)).collect()
+			}
+			ds::ServerMsg::YouNowHaveControl(sid, info) => {
+				let ds::SoldierID(s) = sid;
+				self.agents.insert(s, Agent {
+					me: info,
+					behaviour: None,
+				});
+				self.start_agents()
+			}
+			ds::ServerMsg::SideAssigned(side) => {
+				self.side = Some(side);
+				self.start_agents()
+			}
+			ds::ServerMsg::MapData(map) => {
+				self.bounds = Some(map.bounds);
+				self.start_agents()
+			}
+			ds::ServerMsg::SensorInfo(upd) => {
+				let mut seers: Vec<(ds::SoldierID, ds::SensorUpdate)> = upd.into_iter().collect();
+				seers.sort_by_key(|&(ds::SoldierID(s), _)| s);
+				let mut ret = vec![];
+				for (sid, info) in seers {
+					let ds::SoldierID(s) = sid;
+					match self.agents.get_mut(&s) {
+						Some(agent) => {
+							for (seen, i) in info.insense.iter() {
+								if *seen == sid {
+									agent.me.external = i.clone();
+								}
+							}
+							match agent.behaviour {
+								Some(ref mut b) => ret.extend(b.sensor_update(sid, &agent.me, &info)),
+								None => ()
+							}
+						}
+						None => ()
+					}
+				}
+				ret
+			}
+			ds::ServerMsg::StatusUpdate(ds::SoldierID(s), info) => {
+				match self.agents.get_mut(&s) {
+					Some(agent) => agent.me.internal = info,
+					None => ()
+				}
+				vec![]
+			}
+			ds::ServerMsg::ControlLost(ds::SoldierID(s)) => {
+				self.agents.remove(&s);
+				vec![]
+			}
+			_ => vec![]
+		}
+	}
+
+	fn has_agents(&self) -> bool {
+		!self.agents.is_empty()
+	}
+}
@@ -0,0 +1,22 @@
+extern crate ds;
+
+pub mod behaviour;
+pub mod controller;
+pub mod squad;
+
+pub use crate::behaviour::Behaviour;
+pub use crate::controller::{Commander, Controller};
+pub use crate::squad::Squad;
+
+// the squad coordinates all soldiers of the side, the other behaviours
+// control each soldier on its own
+pub fn create(name: &str) -> Result<Box<dyn Commander>, String> {
+	if name == "squad" {
+		return Ok(Box::new(Squad::new()));
+	}
+	match Controller::new(name) {
+		Ok(c) => Ok(Box::new(c)),
+		Err(_) => Err(format!("unknown behaviour {}, available: squad, {}",
+				      name, behaviour::BEHAVIOURS.join(", ")))
+	}
+}
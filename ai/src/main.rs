@@ -1,65 +1,127 @@
 extern crate ws;
 extern crate serde;
-#[macro_use]
-extern crate serde_derive;
 extern crate rmp_serde;
 
 extern crate ds;
+extern crate ai;
 
 use ws::{connect, CloseCode};
 
-use serde::{Deserialize, Serialize};
-use rmp_serde::{Deserializer, Serializer};
+use serde::Deserialize;
+use rmp_serde::Deserializer;
 
-struct Agent {
-	sid: ds::SoldierID,
-	pos: ds::Position,
-	seen: Vec<(ds::SoldierID, ds::Position)>,
-}
+use ai::Commander;
+
+const USAGE: &str = "Usage: ai [options]
 
+Options:
+    --behaviour <name>               what the soldiers do (default squad)
+    --room <name>                    room to play in, created if it doesn't exist";
+
+struct Options {
+	behaviour: String,
+	room: Option<String>,
+}
 
 struct Client {
 	out: ws::Sender,
-	agents: Vec<Agent>,
+	commander: Box<dyn Commander>,
+	behaviour: String,
+	room: Option<String>,
 }
 
 impl Client {
 	fn send(&mut self, msg: ds::GameMsg) -> ws::Result<()> {
-		let mut buf = Vec::new();
-		msg.serialize(&mut Serializer::new(&mut buf)).unwrap();
-		self.out.send(buf)
+		self.out.send(ds::encode_game_msg(&msg))
 	}
 
-	fn get_agent_mut(&mut self, sid: ds::SoldierID) -> Option<&mut Agent> {
-		for a in &mut self.agents {
-			if a.sid == sid {
-				return Some(&mut *a);
+	fn receive(&mut self, msg: ds::ServerMsg) -> ws::Result<()> {
+		match &msg {
+			ds::ServerMsg::NewGame(soldiers) | ds::ServerMsg::AvailableSoldiers(soldiers) => {
+				if soldiers.is_empty() && !self.commander.has_agents() {
+					return self.out.close(CloseCode::Normal);
+				}
 			}
-		}
-		None
-	}
-
-	fn update_agent<F>(&mut self, sid: ds::SoldierID, action: F)
-		where F: Fn(&mut Agent) -> () {
-		for a in &mut self.agents {
-			if a.sid == sid {
-				action(&mut *a);
-				return;
+			ds::ServerMsg::Welcome { protocol_version } => {
+				println!("Connected, protocol version {}", protocol_version);
+			}
+			ds::ServerMsg::StatusUpdate(sid, info) => {
+				println!("Soldier {:?} health {}", sid, info.health);
+			}
+			ds::ServerMsg::MapData(map) => {
+				println!("Got map with {} roads and {} terrain areas",
+					 map.roads.len(), map.terrain.len());
+			}
+			ds::ServerMsg::SideAssigned(side) => {
+				println!("Playing on side {:?}", side);
+			}
+			ds::ServerMsg::RoomList(rooms) => {
+				for r in rooms {
+					println!("Room {} with {} players", r.name, r.players);
+				}
+			}
+			ds::ServerMsg::JoinedRoom(name) => {
+				println!("Joined room {}", name);
+			}
+			ds::ServerMsg::LeftRoom(name) => {
+				println!("Left room {}", name);
+			}
+			ds::ServerMsg::PlayerList { you, players } => {
+				for (id, side) in players {
+					println!("Player {:?} on side {:?}{}", id, side, if id == you { " (us)" } else { "" });
+				}
 			}
+			ds::ServerMsg::Error { code, request, message } => {
+				println!("Error {:?}: {}", code, message);
+				match request {
+					// the soldiers may have been taken by someone else, see
+					// what is left instead of waiting for control
+					Some(ds::GameMsg::TakeControl(_)) if !self.commander.has_agents() => {
+						self.send(ds::GameMsg::QueryStatus)?;
+					}
+					_ => ()
+				}
+			}
+			_ => ()
+		}
+		let lost = match msg {
+			ds::ServerMsg::ControlLost(_) => true,
+			_ => false
+		};
+		for gmsg in self.commander.receive(msg) {
+			self.send(gmsg)?;
+		}
+		if lost && !self.commander.has_agents() {
+			self.out.close(CloseCode::Normal)
+		} else {
+			Ok(())
 		}
 	}
 }
 
 impl ws::Handler for Client {
 	fn on_open(&mut self, _: ws::Handshake) -> ws::Result<()> {
-		// self.send(ds::GameMsg::Init(1))
+		self.send(ds::GameMsg::Hello {
+			protocol_version: ds::PROTOCOL_VERSION,
+			client_kind: ds::ClientKind::Bot,
+			name: format!("ai ({})", self.behaviour),
+		})?;
+		// self.send(ds::GameMsg::Init(ds::Scenario { seed: 1, soldiers_per_side: 4, map: None }))
+		match self.room.clone() {
+			Some(room) => {
+				// creating an existing room fails, joining it then succeeds
+				self.send(ds::GameMsg::CreateRoom(room.clone()))?;
+				self.send(ds::GameMsg::JoinRoom(room))?;
+			}
+			None => ()
+		}
 		self.send(ds::GameMsg::QueryStatus)
 	}
 
 	fn on_message(&mut self, msg: ws::Message) -> ws::Result<()> {
 		println!("Got message: {}", msg);
 		match msg {
-			ws::Message::Text(t) => {
+			ws::Message::Text(_) => {
 				self.out.close(CloseCode::Normal)
 			}
 			ws::Message::Binary(b) => {
@@ -68,56 +130,7 @@ impl ws::Handler for Client {
 				let msg = Deserialize::deserialize(&mut de);
 				println!("Got msg {:?}", msg);
 				match msg {
-					Ok(ds::ServerMsg::NewGame(soldiers)) => {
-						if soldiers.len() > 0 {
-							self.send(ds::GameMsg::TakeControl(soldiers[0]))
-						} else {
-							self.out.close(CloseCode::Normal)
-						}
-					}
-					Ok(ds::ServerMsg::AvailableSoldiers(soldiers)) => {
-						if soldiers.len() > 0 {
-							self.send(ds::GameMsg::TakeControl(soldiers[0]))
-						} else {
-							self.out.close(CloseCode::Normal)
-						}
-					}
-					Ok(ds::ServerMsg::YouNowHaveControl(sid, info)) => {
-						self.agents.push(Agent {
-							sid: sid,
-							pos: info.external.position,
-							seen: vec![],
-						});
-						self.send(ds::GameMsg::MoveTo(sid,
-									      ds::Position {
-										      x: 5.0,
-										      y: 0.0
-									      }))
-					}
-					Ok(ds::ServerMsg::SensorInfo(upd)) => {
-						let mut closing = false;
-						for (seer, info) in upd {
-							for (seenid, seeninfo) in info.insense {
-								let pos = seeninfo.position;
-								if seenid == seer {
-									self.update_agent(seer, |a| {
-										a.pos = pos;
-									});
-									if pos.dist(&ds::Position {
-										x: 5.0,
-										y: 0.0
-									}) < 1.0 {
-										closing = true;
-									}
-								}
-							}
-						}
-						if closing {
-							self.out.close(CloseCode::Normal)
-						} else {
-							std::result::Result::Ok(())
-						}
-					}
+					Ok(m) => self.receive(m),
 					Err(e) => {
 						println!("Error: {:?}\n", e);
 						self.out.close(CloseCode::Normal)
@@ -128,9 +141,36 @@ impl ws::Handler for Client {
 	}
 }
 
+fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Options, String> {
+	let mut opts = Options {
+		behaviour: "squad".to_string(),
+		room: None,
+	};
+	let mut it = args;
+	while let Some(opt) = it.next() {
+		let val = it.next().ok_or(format!("missing value for {}", opt))?;
+		match opt.as_str() {
+			"--behaviour" => opts.behaviour = val,
+			"--room"      => opts.room = Some(val),
+			_ => return Err(format!("unknown option {}", opt))
+		}
+	}
+	ai::create(&opts.behaviour)?;
+	Ok(opts)
+}
+
 fn main() {
+	let opts = match parse_args(std::env::args().skip(1)) {
+		Ok(o) => o,
+		Err(e) => {
+			eprintln!("{}\n{}", e, USAGE);
+			std::process::exit(1);
+		}
+	};
 	connect("ws://127.0.0.1:8080/ws/", |out| Client {
 		out: out,
-		agents: vec![],
+		commander: ai::create(&opts.behaviour).unwrap(),
+		behaviour: opts.behaviour.clone(),
+		room: opts.room.clone(),
 	}).unwrap()
 }
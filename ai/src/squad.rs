@@ -0,0 +1,397 @@
+extern crate ds;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::controller::{self, Commander};
+
+// distance between soldiers in the line (meters)
+const FORMATION_SPACING: f64 = 10.0;
+// how far the squad advances at a time when there's no contact (meters)
+const LEG_DISTANCE: f64 = 50.0;
+// how far a fire team bounds past the other one under contact (meters)
+const BOUND_DISTANCE: f64 = 20.0;
+// a soldier closer than this to its place in the formation has arrived (meters)
+const SLOT_RADIUS: f64 = 3.0;
+// the whole squad stops to fire once an enemy is this close (meters)
+const FIRING_DISTANCE: f64 = 200.0;
+
+struct Member {
+	info: ds::FullSoldierInfo,
+	// fire team, 0 or 1
+	team: usize,
+	// place in the line, from the left
+	slot: usize,
+	// where the soldier was sent, None if it's holding its position
+	order: Option<ds::Position>,
+	// where the soldier was when it last reported
+	last_pos: Option<ds::Position>,
+	arrived: bool,
+	target: Option<i32>,
+}
+
+// takes control of all soldiers of the side and moves them as one squad
+// in a line abreast towards the enemy's deployment line. The squad forms
+// up once all soldiers it asked for have joined and the map is known.
+// Soldiers taken over after that join at the end of the line. Without
+// contact the whole squad advances a leg at a time, reforming the line
+// after each leg. Under contact the squad splits into two fire teams: one
+// bounds forward while the other holds and fires, then they switch. The
+// whole squad holds and fires when it doesn't outnumber the enemy it sees
+// or once the enemy is close. Every soldier engages the nearest enemy it
+// sees itself, or else the nearest one any soldier of the squad sees.
+pub struct Squad {
+	members: BTreeMap<i32, Member>,
+	// enemies seen by any member
+	enemies: BTreeMap<i32, ds::SeenSoldierInfo>,
+	// which enemies each member sees
+	sightings: BTreeMap<i32, BTreeSet<i32>>,
+	// soldiers asked for but not controlled yet
+	pending: BTreeSet<i32>,
+	// our side is kept from game to game, the map is sent for every game
+	side: Option<ds::Side>,
+	bounds: Option<ds::Position>,
+	// fixed when the squad forms up, None before that
+	objective: Option<ds::Position>,
+	origin: ds::Position,
+	// unit vector towards the objective
+	heading: ds::Position,
+	length: f64,
+	// how far along the way each fire team is
+	progress: [f64; 2],
+	moving: [bool; 2],
+	in_contact: bool,
+}
+
+impl Squad {
+	pub fn new() -> Squad {
+		Squad {
+			members: BTreeMap::new(),
+			enemies: BTreeMap::new(),
+			sightings: BTreeMap::new(),
+			pending: BTreeSet::new(),
+			side: None,
+			bounds: None,
+			objective: None,
+			origin: ds::Position::new(0.0, 0.0),
+			heading: ds::Position::new(1.0, 0.0),
+			length: 0.0,
+			progress: [0.0, 0.0],
+			moving: [false, false],
+			in_contact: false,
+		}
+	}
+
+	// the squad forms up around its current center and heads straight
+	// for the enemy's deployment line
+	fn plan(&mut self) {
+		let n = self.members.len();
+		let front = match controller::enemy_line(self.side, self.bounds) {
+			Some(x) => x,
+			None => return
+		};
+		if n == 0 {
+			return;
+		}
+		let sum = self.members.values().fold(ds::Position::new(0.0, 0.0), |acc, m| {
+			ds::Position::new(acc.x + m.info.external.position.x, acc.y + m.info.external.position.y)
+		});
+		let center = ds::Position::new(sum.x / n as f64, sum.y / n as f64);
+		let objective = ds::Position::new(front, center.y);
+		self.objective = Some(objective);
+		self.origin = center;
+		self.length = center.dist(&objective);
+		if self.length > 0.0 {
+			self.heading = ds::Position::new(objective.x - center.x, objective.y - center.y).normalized();
+		}
+		// places in the line are taken from left to right so no one crosses paths
+		let (origin, heading) = (self.origin, self.heading);
+		let lateral = |p: &ds::Position| heading.x * (p.y - origin.y) - heading.y * (p.x - origin.x);
+		let mut order: Vec<(i32, f64)> = self.members.iter()
+			.map(|(s, m)| (*s, lateral(&m.info.external.position)))
+			.collect();
+		order.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+		for (i, (s, _)) in order.into_iter().enumerate() {
+			let m = self.members.get_mut(&s).unwrap();
+			m.slot = i;
+			m.team = i % 2;
+		}
+	}
+
+	// later soldiers take the next place in the line
+	fn joined(&mut self, s: i32) {
+		self.pending.remove(&s);
+		if self.objective.is_some() {
+			let slot = self.members.iter()
+				.filter(|(o, _)| **o != s)
+				.map(|(_, m)| m.slot + 1)
+				.max()
+				.unwrap_or(0);
+			match self.members.get_mut(&s) {
+				Some(m) => {
+					m.slot = slot;
+					m.team = slot % 2;
+				}
+				None => ()
+			}
+		}
+	}
+
+	fn slot_position(&self, slot: usize, team: usize) -> ds::Position {
+		let lateral = (slot as f64 - (self.members.len() - 1) as f64 * 0.5) * FORMATION_SPACING;
+		let along = self.progress[team];
+		ds::Position::new(self.origin.x + self.heading.x * along - self.heading.y * lateral,
+				  self.origin.y + self.heading.y * along + self.heading.x * lateral)
+	}
+
+	fn team_size(&self, team: usize) -> usize {
+		self.members.values().filter(|m| m.team == team).count()
+	}
+
+	// how far along the way the team's soldiers are on average
+	fn team_progress(&self, team: usize) -> f64 {
+		let along: Vec<f64> = self.members.values()
+			.filter(|m| m.team == team)
+			.map(|m| {
+				let p = m.info.external.position;
+				(p.x - self.origin.x) * self.heading.x + (p.y - self.origin.y) * self.heading.y
+			})
+			.collect();
+		if along.is_empty() {
+			self.progress[team]
+		} else {
+			(along.iter().sum::<f64>() / along.len() as f64).max(0.0).min(self.length)
+		}
+	}
+
+	fn next_bound(&mut self) {
+		let contact = self.enemies.values().any(|e| e.alive);
+		if contact && !self.in_contact {
+			// stop where we are and start bounding
+			for t in 0..2 {
+				self.progress[t] = self.team_progress(t);
+				self.moving[t] = false;
+			}
+		}
+		self.in_contact = contact;
+		// only bound forward while outnumbering the enemy
+		let enemies = self.enemies.values().filter(|e| e.alive).count();
+		if contact && (self.members.len() <= enemies || self.enemy_distance() <= FIRING_DISTANCE) {
+			self.moving = [false, false];
+			return;
+		}
+
+		for t in 0..2 {
+			let done = self.members.values().filter(|m| m.team == t).all(|m| m.arrived);
+			if self.moving[t] && done {
+				self.moving[t] = false;
+			}
+		}
+		if self.moving[0] || self.moving[1] {
+			return;
+		}
+
+		let front = self.progress[0].max(self.progress[1]);
+		if contact {
+			// the team behind bounds past the other one
+			let mut t = if self.progress[1] < self.progress[0] { 1 } else { 0 };
+			if self.team_size(t) == 0 {
+				t = 1 - t;
+			}
+			let p = if self.team_size(1 - t) == 0 {
+				self.progress[t] + BOUND_DISTANCE
+			} else {
+				front + BOUND_DISTANCE
+			}.min(self.length);
+			if p > self.progress[t] {
+				self.progress[t] = p;
+				self.moving[t] = true;
+			}
+		} else {
+			let p = (front + LEG_DISTANCE).min(self.length);
+			for t in 0..2 {
+				if p > self.progress[t] {
+					self.progress[t] = p;
+					self.moving[t] = true;
+				}
+			}
+		}
+	}
+
+	// distance between the closest member and enemy
+	fn enemy_distance(&self) -> f64 {
+		let mut dist = std::f64::INFINITY;
+		for m in self.members.values() {
+			for e in self.enemies.values().filter(|e| e.alive) {
+				dist = dist.min(m.info.external.position.dist(&e.position));
+			}
+		}
+		dist
+	}
+
+	fn nearest_enemy(&self, sid: i32, pos: &ds::Position) -> Option<i32> {
+		let alive: Vec<(&i32, &ds::SeenSoldierInfo)> = self.enemies.iter().filter(|(_, e)| e.alive).collect();
+		let nearest = |own: bool| alive.iter()
+			.filter(|(s, _)| !own || self.sightings.get(&sid).map_or(false, |seen| seen.contains(s)))
+			.min_by(|(_, a), (_, b)| pos.dist(&a.position).partial_cmp(&pos.dist(&b.position)).unwrap())
+			.map(|(s, _)| **s);
+		nearest(true).or_else(|| nearest(false))
+	}
+
+	fn orders(&mut self) -> Vec<ds::GameMsg> {
+		// forms up once no more soldiers are expected
+		if self.objective.is_none() && self.pending.is_empty() {
+			self.plan();
+		}
+		if self.objective.is_some() {
+			self.next_bound();
+		}
+		let mut ret = vec![];
+		let sids: Vec<i32> = self.members.keys().cloned().collect();
+		for s in sids {
+			let sid = ds::SoldierID(s);
+			let (slot, team, pos) = {
+				let m = &self.members[&s];
+				(m.slot, m.team, m.info.external.position)
+			};
+			let dest = if self.moving[team] {
+				Some(self.slot_position(slot, team))
+			} else {
+				None
+			};
+			let target = self.nearest_enemy(s, &pos);
+			let m = self.members.get_mut(&s).unwrap();
+			match (dest, m.order) {
+				(Some(d), Some(o)) if d == o => (),
+				(Some(d), _) => {
+					m.order = Some(d);
+					m.arrived = false;
+					m.last_pos = None;
+					ret.push(ds::GameMsg::MoveTo(sid, d));
+				}
+				(None, Some(_)) => {
+					// halt to fire more accurately
+					m.order = None;
+					m.arrived = true;
+					ret.push(ds::GameMsg::MoveTo(sid, pos));
+				}
+				(None, None) => ()
+			}
+			if target.is_some() && target != m.target {
+				ret.push(ds::GameMsg::Engage(sid, ds::SoldierID(target.unwrap())));
+			}
+			m.target = target;
+		}
+		ret
+	}
+
+	fn sensed(&mut self, seer: i32, upd: ds::SensorUpdate) {
+		let side = self.side;
+		let seen = self.sightings.entry(seer).or_insert(BTreeSet::new());
+		for (ds::SoldierID(s), info) in upd.insense {
+			if Some(info.side) != side {
+				seen.insert(s);
+				self.enemies.insert(s, info);
+			} else if s == seer {
+				match self.members.get_mut(&s) {
+					Some(m) => {
+						let pos = info.position;
+						if let Some(o) = m.order {
+							// a soldier that stopped short of its place is stuck there
+							m.arrived = pos.dist(&o) < SLOT_RADIUS || m.last_pos == Some(pos);
+						}
+						m.last_pos = Some(pos);
+						m.info.external = info;
+					}
+					None => ()
+				}
+			}
+		}
+		for ds::SoldierID(s) in upd.outsense {
+			seen.remove(&s);
+		}
+		self.forget_unseen();
+	}
+
+	// enemies no member sees anymore are dropped from the picture
+	fn forget_unseen(&mut self) {
+		let sightings = &self.sightings;
+		self.enemies.retain(|s, _| sightings.values().any(|seen| seen.contains(s)));
+	}
+}
+
+impl Commander for Squad {
+	fn receive(&mut self, msg: ds::ServerMsg) -> Vec<ds::GameMsg> {
+		match msg {
+			ds::ServerMsg::NewGame(soldiers) => {
+				self.members.clear();
+				self.enemies.clear();
+				self.sightings.clear();
+				self.objective = None;
+				self.bounds = None;
+				self.progress = [0.0, 0.0];
+				self.moving = [false, false];
+				self.in_contact = false;
+				self.pending = soldiers.iter().map(|&ds::SoldierID(s)| s).collect();
+				soldiers.into_iter().map(|s| ds::GameMsg::TakeControl(s)).collect()
+			}
+			ds::ServerMsg::AvailableSoldiers(soldiers) => {
+				self.pending.extend(soldiers.iter().map(|&ds::SoldierID(s)| s));
+				soldiers.into_iter().map(|s| ds::GameMsg::TakeControl(s)).collect()
+			}
+			ds::ServerMsg::YouNowHaveControl(ds::SoldierID(s), info) => {
+				self.members.insert(s, Member {
+					info: info,
+					team: 0,
+					slot: 0,
+					order: None,
+					last_pos: None,
+					arrived: true,
+					target: None,
+				});
+				self.joined(s);
+				self.orders()
+			}
+			ds::ServerMsg::Error { request: Some(ds::GameMsg::TakeControl(ds::SoldierID(s))), .. } => {
+				// someone else got the soldier
+				self.joined(s);
+				self.orders()
+			}
+			ds::ServerMsg::SideAssigned(side) => {
+				self.side = Some(side);
+				self.orders()
+			}
+			ds::ServerMsg::MapData(map) => {
+				self.bounds = Some(map.bounds);
+				self.orders()
+			}
+			ds::ServerMsg::SensorInfo(upd) => {
+				let mut seers: Vec<(ds::SoldierID, ds::SensorUpdate)> = upd.into_iter().collect();
+				seers.sort_by_key(|&(ds::SoldierID(s), _)| s);
+				for (ds::SoldierID(s), info) in seers {
+					if self.members.contains_key(&s) {
+						self.sensed(s, info);
+					}
+				}
+				self.orders()
+			}
+			ds::ServerMsg::StatusUpdate(ds::SoldierID(s), info) => {
+				match self.members.get_mut(&s) {
+					Some(m) => m.info.internal = info,
+					None => ()
+				}
+				vec![]
+			}
+			ds::ServerMsg::ControlLost(ds::SoldierID(s)) => {
+				self.members.remove(&s);
+				self.sightings.remove(&s);
+				self.forget_unseen();
+				self.orders()
+			}
+			_ => vec![]
+		}
+	}
+
+	fn has_agents(&self) -> bool {
+		!self.members.is_empty()
+	}
+}
@@ -0,0 +1,99 @@
+extern crate ds;
+extern crate ai;
+
+use std::collections::HashMap;
+
+fn soldier(x: f64, y: f64) -> ds::FullSoldierInfo {
+	ds::FullSoldierInfo {
+		internal: ds::InternalSoldierInfo { health: 100 },
+		external: ds::SeenSoldierInfo {
+			alive: true,
+			position: ds::Position::new(x, y),
+			direction: ds::Direction(0.0),
+			side: ds::Side::Red,
+		},
+	}
+}
+
+fn destinations(msgs: Vec<ds::GameMsg>) -> Vec<(i32, ds::Position)> {
+	msgs.into_iter().filter_map(|m| match m {
+		ds::GameMsg::MoveTo(ds::SoldierID(s), pos) => Some((s, pos)),
+		_ => None
+	}).collect()
+}
+
+// an empty map of the given size
+fn map(size: f64) -> ds::ServerMsg {
+	ds::ServerMsg::MapData(ds::MapData {
+		roads: vec![],
+		terrain: vec![],
+		bounds: ds::Position::new(size, size),
+	})
+}
+
+#[test]
+fn soldiers_taken_over_later_advance_to_the_same_line() {
+	let mut c = ai::create("advance").unwrap();
+	c.receive(ds::ServerMsg::SideAssigned(ds::Side::Red));
+	c.receive(ds::ServerMsg::NewGame(vec![ds::SoldierID(0)]));
+	c.receive(map(1000.0));
+	let msgs = c.receive(ds::ServerMsg::YouNowHaveControl(ds::SoldierID(0), soldier(-200.0, 5.0)));
+	assert_eq!(destinations(msgs), vec![(0, ds::Position::new(200.0, 5.0))]);
+	// a soldier that had already walked past the center
+	let msgs = c.receive(ds::ServerMsg::YouNowHaveControl(ds::SoldierID(1), soldier(50.0, -20.0)));
+	assert_eq!(destinations(msgs), vec![(1, ds::Position::new(200.0, -20.0))]);
+	// the other side deploys closer on a smaller map. Soldiers we get
+	// before the map wait for it.
+	c.receive(ds::ServerMsg::NewGame(vec![ds::SoldierID(0)]));
+	let msgs = c.receive(ds::ServerMsg::YouNowHaveControl(ds::SoldierID(0), soldier(-100.0, 0.0)));
+	assert_eq!(destinations(msgs), vec![]);
+	let msgs = c.receive(map(250.0));
+	assert_eq!(destinations(msgs), vec![(0, ds::Position::new(100.0, 0.0))]);
+}
+
+// each soldier sees itself at the given place
+fn positions(soldiers: &[(i32, f64, f64)]) -> ds::ServerMsg {
+	let mut upd = HashMap::new();
+	for &(s, x, y) in soldiers {
+		let mut sensed = ds::SensorUpdate::new();
+		sensed.insense.push((ds::SoldierID(s), soldier(x, y).external));
+		upd.insert(ds::SoldierID(s), sensed);
+	}
+	ds::ServerMsg::SensorInfo(upd)
+}
+
+#[test]
+fn squads_keep_going_when_soldiers_join() {
+	let mut c = ai::create("squad").unwrap();
+	c.receive(ds::ServerMsg::SideAssigned(ds::Side::Red));
+	c.receive(ds::ServerMsg::NewGame(vec![ds::SoldierID(0), ds::SoldierID(1)]));
+	c.receive(map(1000.0));
+	// the squad waits for everyone before forming up
+	let msgs = c.receive(ds::ServerMsg::YouNowHaveControl(ds::SoldierID(0), soldier(-200.0, -5.0)));
+	assert_eq!(destinations(msgs), vec![]);
+	let msgs = c.receive(ds::ServerMsg::YouNowHaveControl(ds::SoldierID(1), soldier(-200.0, 5.0)));
+	assert_eq!(destinations(msgs), vec![(0, ds::Position::new(-150.0, -5.0)), (1, ds::Position::new(-150.0, 5.0))]);
+	let msgs = c.receive(positions(&[(0, -150.0, -5.0), (1, -150.0, 5.0)]));
+	assert_eq!(destinations(msgs), vec![(0, ds::Position::new(-100.0, -5.0)), (1, ds::Position::new(-100.0, 5.0))]);
+	// a soldier taken over later joins the line where it is now
+	let msgs = c.receive(ds::ServerMsg::YouNowHaveControl(ds::SoldierID(2), soldier(-200.0, 40.0)));
+	let dests = destinations(msgs);
+	assert!(dests.iter().any(|&(s, _)| s == 2));
+	for (_, pos) in dests {
+		assert_eq!(pos.x, -100.0);
+	}
+}
+
+#[test]
+fn squads_head_for_the_enemy_deployment_line() {
+	let mut c = ai::create("squad").unwrap();
+	c.receive(ds::ServerMsg::SideAssigned(ds::Side::Blue));
+	c.receive(ds::ServerMsg::NewGame(vec![ds::SoldierID(4)]));
+	// the squad waits for the map before forming up
+	let msgs = c.receive(ds::ServerMsg::YouNowHaveControl(ds::SoldierID(4), soldier(30.0, 0.0)));
+	assert_eq!(destinations(msgs), vec![]);
+	// a soldier that had already walked past the center still advances
+	// towards the red line
+	let msgs = c.receive(map(1000.0));
+	assert_eq!(destinations(msgs), vec![(4, ds::Position::new(-20.0, 0.0))]);
+}
@@ -0,0 +1,239 @@
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate ds;
+extern crate sim;
+extern crate ai;
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use sim::{ClientId, GameState};
+use sim::config::{GameConfig, MapConfig};
+use sim::osm::MapData;
+
+use ai::Commander;
+
+// how many times the sides may answer each other between ticks
+const MAX_ROUNDS: usize = 16;
+
+const USAGE: &str = "Usage: batch [options]
+
+Runs matches between two AI behaviours without real-time ticking.
+
+Options:
+    --matches <n>                    number of matches to run (default 10)
+    --red <behaviour>                behaviour of the red soldiers (default advance)
+    --blue <behaviour>               behaviour of the blue soldiers (default advance)
+    --seed <n>                       seed of the first match, incremented for each match (default 1)
+    --soldiers <n>                   number of soldiers per side (default 4)
+    --max-ticks <n>                  matches not decided by then are draws (default 6000)
+    --map <file.osm>                 map to use, a flat map is used if not given
+    --bbox <minlat,minlon,maxlat,maxlon>
+                                     use only this area of the map
+    --format <csv|json>              output format (default csv)";
+
+struct Options {
+	matches: u64,
+	red: String,
+	blue: String,
+	seed: u64,
+	max_ticks: u64,
+	json: bool,
+	game: GameConfig,
+	map: MapConfig,
+}
+
+#[derive(Serialize)]
+struct MatchResult {
+	seed: u64,
+	winner: String,
+	ticks: u64,
+	// simulated length of the match (seconds)
+	duration: f64,
+	red_casualties: i32,
+	blue_casualties: i32,
+}
+
+#[derive(Serialize)]
+struct Summary {
+	matches: u64,
+	red_behaviour: String,
+	blue_behaviour: String,
+	red_win_rate: f64,
+	blue_win_rate: f64,
+	draw_rate: f64,
+	mean_ticks: f64,
+	mean_red_casualties: f64,
+	mean_blue_casualties: f64,
+}
+
+#[derive(Serialize)]
+struct Report {
+	summary: Summary,
+	matches: Vec<MatchResult>,
+}
+
+fn parse<T: FromStr>(opt: &str, val: String) -> Result<T, String> {
+	val.parse().map_err(|_| format!("invalid value for {}: {}", opt, val))
+}
+
+fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Options, String> {
+	let mut opts = Options {
+		matches: 10,
+		red: "advance".to_string(),
+		blue: "advance".to_string(),
+		seed: 1,
+		max_ticks: 6000,
+		json: false,
+		game: GameConfig::default(),
+		map: MapConfig::default(),
+	};
+	let mut it = args;
+	while let Some(opt) = it.next() {
+		let val = it.next().ok_or(format!("missing value for {}", opt))?;
+		match opt.as_str() {
+			"--matches"   => opts.matches = parse(&opt, val)?,
+			"--red"       => opts.red = val,
+			"--blue"      => opts.blue = val,
+			"--seed"      => opts.seed = parse(&opt, val)?,
+			"--soldiers"  => opts.game.soldiers_per_side = parse(&opt, val)?,
+			"--max-ticks" => opts.max_ticks = parse(&opt, val)?,
+			"--map"       => opts.map.path = Some(val),
+			"--bbox"      => opts.map.bbox = Some(val.parse()?),
+			"--format"    => {
+				opts.json = match val.as_str() {
+					"csv"  => false,
+					"json" => true,
+					_      => return Err(format!("unknown format {}", val))
+				}
+			}
+			_ => return Err(format!("unknown option {}", opt))
+		}
+	}
+	for b in [&opts.red, &opts.blue].iter() {
+		ai::create(b)?;
+	}
+	if opts.game.soldiers_per_side < 1 || opts.game.soldiers_per_side > ds::MAX_NUM_SOLDIERS / 2 {
+		return Err(format!("soldiers per side must be between 1 and {}", ds::MAX_NUM_SOLDIERS / 2));
+	}
+	opts.map.validate()?;
+	Ok(opts)
+}
+
+fn alive(game: &GameState, side: ds::Side) -> i32 {
+	game.full_state().iter()
+		.filter(|(_, info)| info.external.alive && info.external.side == side)
+		.count() as i32
+}
+
+// passes messages between the game and the sides until they are done
+fn deliver(game: &mut GameState, sides: &mut [Box<dyn Commander>]) {
+	for _ in 0..MAX_ROUNDS {
+		let msgs = game.take_messages();
+		if msgs.is_empty() {
+			return;
+		}
+		for (ClientId(c), msg) in msgs {
+			for gmsg in sides[c as usize].receive(msg) {
+				game.game_msg(ClientId(c), gmsg);
+			}
+		}
+	}
+}
+
+fn run_match(map: Arc<MapData>, opts: &Options, seed: u64) -> MatchResult {
+	let mut game = GameState::new(map, opts.game.clone(), seed);
+	let mut sides = vec![ai::create(&opts.red).unwrap(), ai::create(&opts.blue).unwrap()];
+	for (i, side) in [ds::Side::Red, ds::Side::Blue].iter().enumerate() {
+		game.set_client_side(ClientId(i as u64), *side);
+		game.game_msg(ClientId(i as u64), ds::GameMsg::QueryStatus);
+	}
+	let (red, blue) = loop {
+		deliver(&mut game, &mut sides);
+		let red = alive(&game, ds::Side::Red);
+		let blue = alive(&game, ds::Side::Blue);
+		if red == 0 || blue == 0 || game.ticks() >= opts.max_ticks {
+			break (red, blue);
+		}
+		game.tick();
+	};
+	let winner = if red > 0 && blue == 0 {
+		"red"
+	} else if blue > 0 && red == 0 {
+		"blue"
+	} else {
+		"draw"
+	};
+	let step = game.timestep();
+	MatchResult {
+		seed: seed,
+		winner: winner.to_string(),
+		ticks: game.ticks(),
+		duration: game.ticks() as f64 * (step.as_secs() as f64 + step.subsec_nanos() as f64 * 1e-9),
+		red_casualties: opts.game.soldiers_per_side - red,
+		blue_casualties: opts.game.soldiers_per_side - blue,
+	}
+}
+
+fn summarize(opts: &Options, results: &[MatchResult]) -> Summary {
+	let n = results.len().max(1) as f64;
+	let rate = |w: &str| results.iter().filter(|r| r.winner == w).count() as f64 / n;
+	Summary {
+		matches: results.len() as u64,
+		red_behaviour: opts.red.clone(),
+		blue_behaviour: opts.blue.clone(),
+		red_win_rate: rate("red"),
+		blue_win_rate: rate("blue"),
+		draw_rate: rate("draw"),
+		mean_ticks: results.iter().map(|r| r.ticks as f64).sum::<f64>() / n,
+		mean_red_casualties: results.iter().map(|r| r.red_casualties as f64).sum::<f64>() / n,
+		mean_blue_casualties: results.iter().map(|r| r.blue_casualties as f64).sum::<f64>() / n,
+	}
+}
+
+fn main() {
+	let opts = match parse_args(std::env::args().skip(1)) {
+		Ok(o) => o,
+		Err(e) => {
+			eprintln!("{}\n{}", e, USAGE);
+			std::process::exit(1);
+		}
+	};
+	let map = match sim::osm::load_map(&opts.map) {
+		Ok(m) => Arc::new(m),
+		Err(e) => {
+			eprintln!("Error loading map: {}", e);
+			std::process::exit(1);
+		}
+	};
+
+	if !opts.json {
+		println!("seed,winner,ticks,duration,red_casualties,blue_casualties");
+	}
+	let mut results = vec![];
+	for i in 0..opts.matches {
+		let res = run_match(map.clone(), &opts, opts.seed.wrapping_add(i));
+		if !opts.json {
+			println!("{},{},{},{:.1},{},{}", res.seed, res.winner, res.ticks, res.duration,
+				 res.red_casualties, res.blue_casualties);
+		}
+		results.push(res);
+	}
+
+	let summary = summarize(&opts, &results);
+	if opts.json {
+		let report = Report {
+			summary: summary,
+			matches: results,
+		};
+		println!("{}", serde_json::to_string_pretty(&report).unwrap());
+	} else {
+		// keep the CSV on stdout clean
+		eprintln!("red {} vs blue {}: red wins {:.1}%, blue wins {:.1}%, draws {:.1}%",
+			  summary.red_behaviour, summary.blue_behaviour,
+			  summary.red_win_rate * 100.0, summary.blue_win_rate * 100.0, summary.draw_rate * 100.0);
+		eprintln!("mean length {:.0} ticks, mean casualties red {:.2}, blue {:.2}",
+			  summary.mean_ticks, summary.mean_red_casualties, summary.mean_blue_casualties);
+	}
+}
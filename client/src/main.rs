@@ -14,7 +14,7 @@ use stdweb::unstable::TryInto;
 use failure::Error;
 
 use yew::prelude::*;
-use yew::format::{Json, MsgPack};
+use yew::format::MsgPack;
 use yew::services::ConsoleService;
 use yew::services::websocket::{WebSocketService, WebSocketStatus, WebSocketTask};
 
@@ -34,7 +34,10 @@ struct Model {
 	ctx: stdweb::web::CanvasRenderingContext2d,
 	view_center: ds::Position,
 	sid: Option<ds::SoldierID>,
+	side: Option<ds::Side>,
 	seen: HashMap<ds::SoldierID, ds::SeenSoldierInfo>,
+	map: Option<ds::MapData>,
+	observer: bool,                  // spectating, everything is seen
 }
 
 enum Msg {
@@ -53,18 +56,80 @@ fn text_to_gamemsg(text: &String) -> Option<ds::GameMsg> {
 	let spl: Vec<&str> = text.split(" ").collect();
 	match spl[0] {
 		"/init" => {
-			spl.get(1)
-				.and_then(|s| s.parse().ok())
-				.and_then(|n| Some(ds::GameMsg::Init(n)))
+			// /init seed [soldiers per side] [map]
+			let seed = spl.get(1).and_then(|s| s.parse().ok());
+			let soldiers = match spl.get(2) {
+				Some(s) => s.parse().ok(),
+				None => Some(4)
+			};
+			seed.and_then(|seed| soldiers
+				      .and_then(|soldiers| Some(ds::GameMsg::Init(ds::Scenario {
+					      seed: seed,
+					      soldiers_per_side: soldiers,
+					      map: spl.get(3).map(|m| m.to_string()),
+				      }))))
+		}
+		"/auth" => {
+			spl.get(1).map(|s| ds::GameMsg::Authenticate(s.to_string()))
 		}
 		"/control" => {
 			spl.get(1)
 				.and_then(|s| s.parse().ok())
 				.and_then(|n| Some(ds::GameMsg::TakeControl(ds::SoldierID(n))))
 		}
+		"/release" => {
+			spl.get(1)
+				.and_then(|s| s.parse().ok())
+				.map(|n| ds::GameMsg::ReleaseControl(ds::SoldierID(n)))
+		}
+		"/transfer" => {
+			// /transfer soldier player
+			let sid = spl.get(1).and_then(|s| s.parse().ok());
+			let player = spl.get(2).and_then(|s| s.parse().ok());
+			sid.and_then(|sid| player
+				     .map(|p| ds::GameMsg::TransferControl(ds::SoldierID(sid), ds::PlayerId(p))))
+		}
+		"/players" => {
+			Some(ds::GameMsg::ListPlayers)
+		}
 		"/query" => {
 			Some(ds::GameMsg::QueryStatus)
 		}
+		"/side" => {
+			match spl.get(1) {
+				Some(&"red")  => Some(ds::GameMsg::ChooseSide(ds::Side::Red)),
+				Some(&"blue") => Some(ds::GameMsg::ChooseSide(ds::Side::Blue)),
+				_             => None
+			}
+		}
+		"/rooms" => {
+			Some(ds::GameMsg::ListRooms)
+		}
+		"/create" => {
+			spl.get(1).map(|s| ds::GameMsg::CreateRoom(s.to_string()))
+		}
+		"/join" => {
+			spl.get(1).map(|s| ds::GameMsg::JoinRoom(s.to_string()))
+		}
+		"/leave" => {
+			Some(ds::GameMsg::LeaveRoom)
+		}
+		"/spectate" => {
+			Some(ds::GameMsg::Spectate)
+		}
+		"/engage" => {
+			let vc = spl.get(1..3);
+			match vc {
+				Some([s1, s2]) => {
+					let n1 = s1.parse().ok();
+					let n2 = s2.parse().ok();
+					n1.and_then(|n1| n2
+						    .and_then(|n2| Some(ds::GameMsg::Engage(ds::SoldierID(n1),
+							      ds::SoldierID(n2)))))
+				}
+				_ => None
+			}
+		}
 		"/move" => {
 			let vc = spl.get(1..4);
 			match vc {
@@ -139,22 +204,83 @@ impl Model {
 		self.canvas_scale = 0.05;
 	}
 
+	// forget the state of the game we were in
+	fn leave_game(&mut self) {
+		self.sid = None;
+		self.side = None;
+		self.observer = false;
+		self.seen = HashMap::new();
+		self.map = None;
+		self.update_canvas();
+	}
+
 	fn update_canvas(&self) {
 		self.ctx.set_fill_style_color("black");
 		self.ctx.fill_rect(0.0, 0.0,
 				   self.canvas.width().into(),
 				   self.canvas.height().into());
-		self.ctx.set_fill_style_color("green");
 		let canv = self.canvas_dimensions.0.min(
 			self.canvas_dimensions.1);
 		let width = canv * 0.05;
 		let edge_x = self.view_center.x - (self.canvas_dimensions.0 * self.canvas_scale) * 0.5;
 		let edge_y = self.view_center.y - (self.canvas_dimensions.1 * self.canvas_scale) * 0.5;
-		for (_, info) in &self.seen {
+		if let Some(ref map) = self.map {
+			self.draw_map(map, edge_x, edge_y);
+		}
+		for (sid, info) in &self.seen {
+			if Some(*sid) == self.sid {
+				self.ctx.set_fill_style_color("green");
+			} else {
+				match info.side {
+					ds::Side::Red  => self.ctx.set_fill_style_color("red"),
+					ds::Side::Blue => self.ctx.set_fill_style_color("blue"),
+				}
+			}
 			self.draw_soldier(info, width, edge_x, edge_y);
 		}
 	}
 
+	fn trace_line(&self, line: &Vec<ds::Position>, edge_x: f64, edge_y: f64) {
+		for (i, pos) in line.iter().enumerate() {
+			let xp = (pos.x - edge_x) / self.canvas_scale;
+			let yp = (pos.y - edge_y) / self.canvas_scale;
+			if i == 0 {
+				self.ctx.move_to(xp, yp);
+			} else {
+				self.ctx.line_to(xp, yp);
+			}
+		}
+	}
+
+	fn draw_map(&self, map: &ds::MapData, edge_x: f64, edge_y: f64) {
+		for area in &map.terrain {
+			self.ctx.set_fill_style_color(match area.kind {
+				ds::TerrainKind::Forest   => "#0b3d0b",
+				ds::TerrainKind::Water    => "#123a6b",
+				ds::TerrainKind::Building => "#505050",
+				ds::TerrainKind::Field    => "#3a3a12",
+				ds::TerrainKind::Urban    => "#242424",
+			});
+			for poly in &area.polygons {
+				self.ctx.begin_path();
+				self.trace_line(&poly.exterior, edge_x, edge_y);
+				self.ctx.close_path();
+				for interior in &poly.interiors {
+					self.trace_line(interior, edge_x, edge_y);
+					self.ctx.close_path();
+				}
+				self.ctx.fill(stdweb::web::FillRule::EvenOdd);
+			}
+		}
+		self.ctx.set_stroke_style_color("#808080");
+		self.ctx.set_line_width(2.0);
+		for road in &map.roads {
+			self.ctx.begin_path();
+			self.trace_line(road, edge_x, edge_y);
+			self.ctx.stroke();
+		}
+	}
+
 	fn draw_soldier(&self, info: &ds::SeenSoldierInfo, width: f64, edge_x: f64, edge_y: f64) {
 		let pos = info.position;
 		let xp = (pos.x - edge_x) / self.canvas_scale;
@@ -176,9 +302,22 @@ impl Model {
 		let yp = (yo - self.canvas_dimensions.1 * 0.5) * self.canvas_scale + self.view_center.y;
 		self.console.log(&format!("xo: {}, yo: {}", xo, yo));
 		self.console.log(&format!("xp: {}, yp: {}", xp, yp));
-		if let Some(sid) = self.sid {
+		if self.observer {
+			// observers look around by clicking
+			self.view_center = ds::Position::new(xp, yp);
+			self.update_canvas();
+		} else if let Some(sid) = self.sid {
+			let clicked = ds::Position::new(xp, yp);
+			// clicking on an enemy soldier engages it, elsewhere moves there
+			let enemy = self.seen.iter()
+				.find(|(_, info)| Some(info.side) != self.side && info.alive &&
+				      info.position.dist(&clicked) < 2.0)
+				.map(|(esid, _)| *esid);
+			let msg = match enemy {
+				Some(esid) => ds::GameMsg::Engage(sid, esid),
+				None       => ds::GameMsg::MoveTo(sid, clicked),
+			};
 			if let Some(ref mut task) = self.ws {
-				let msg = ds::GameMsg::MoveTo(sid, ds::Position::new(xp, yp));
 				task.send_binary(MsgPack(&msg));
 			}
 		}
@@ -209,7 +348,10 @@ impl Component for Model {
 			ctx: ct,
 			view_center: ds::Position::new(0.0, 0.0),
 			sid: None,
+			side: None,
 			seen: HashMap::new(),
+			map: None,
+			observer: false,
 		}
 	}
 
@@ -225,7 +367,11 @@ impl Component for Model {
 							Msg::Disconnected
 						}
 						WebSocketStatus::Opened => {
-							Msg::SendGameMsg(ds::GameMsg::QueryStatus)
+							Msg::SendGameMsg(ds::GameMsg::Hello {
+								protocol_version: ds::PROTOCOL_VERSION,
+								client_kind: ds::ClientKind::Web,
+								name: "web".to_string(),
+							})
 						}
 					}
 				});
@@ -259,7 +405,9 @@ impl Component for Model {
 								task.send_binary(MsgPack(&msg));
 							}
 							None => {
-								task.send(Json(&self.text));
+								// anything else goes as is, the server
+								// takes JSON messages in text frames
+								task.send(Ok::<String, Error>(self.text.clone()));
 							}
 						}
 						self.text = "".to_string();
@@ -280,13 +428,27 @@ impl Component for Model {
 			}
 			Msg::Received(m) => {
 				match m {
-					ds::ServerMsg::NewGame(_) => {
+					ds::ServerMsg::Welcome { protocol_version } => {
+						self.server_data.push_str(&format!("Connected, protocol version {}\n", protocol_version));
+						self.link.send_self(Msg::SendGameMsg(ds::GameMsg::QueryStatus));
+						true
+					}
+					ds::ServerMsg::NewGame(s) => {
 						self.sid = None;
 						self.seen = HashMap::new();
+						self.server_data.push_str("New game started\n");
+						if self.observer {
+							self.observer = false;
+							self.server_data.push_str("Not spectating anymore, choose a side or spectate again\n");
+						}
+						if s.len() > 0 && !self.observer {
+							self.link.send_self(Msg::SendGameMsg(
+									ds::GameMsg::TakeControl(s[0])));
+						}
 						true
 					}
 					ds::ServerMsg::AvailableSoldiers(s) => {
-						if s.len() > 0 {
+						if s.len() > 0 && !self.observer {
 							self.link.send_self(Msg::SendGameMsg(
 									ds::GameMsg::TakeControl(s[0])));
 						}
@@ -310,12 +472,92 @@ impl Component for Model {
 						}
 						false
 					}
-					ds::ServerMsg::YouNowHaveControl(sid, info) => {
+					ds::ServerMsg::SideAssigned(side) => {
+						self.server_data.push_str(&format!("Playing on side {:?}\n", side));
+						if self.observer {
+							// back to playing
+							self.observer = false;
+							self.seen = HashMap::new();
+						} else if self.side.is_some() && self.side != Some(side) {
+							self.sid = None;
+							self.seen = HashMap::new();
+						}
+						self.side = Some(side);
+						true
+					}
+					ds::ServerMsg::MapData(map) => {
+						self.map = Some(map);
+						self.update_canvas();
+						false
+					}
+					ds::ServerMsg::StatusUpdate(sid, info) => {
+						self.server_data.push_str(&format!("Soldier {:?} health {}\n", sid, info.health));
+						true
+					}
+					ds::ServerMsg::ControlLost(sid) => {
+						self.server_data.push_str(&format!("Lost control of soldier {:?}\n", sid));
+						if self.sid == Some(sid) {
+							self.sid = None;
+						}
+						true
+					}
+					ds::ServerMsg::RoomList(rooms) => {
+						for r in rooms {
+							self.server_data.push_str(&format!("Room {} with {} players\n", r.name, r.players));
+						}
+						true
+					}
+					ds::ServerMsg::JoinedRoom(name) => {
+						self.server_data.push_str(&format!("Joined room {}\n", name));
+						self.leave_game();
+						true
+					}
+					ds::ServerMsg::LeftRoom(name) => {
+						self.server_data.push_str(&format!("Left room {}\n", name));
+						self.leave_game();
+						true
+					}
+					ds::ServerMsg::FullState(soldiers) => {
+						if !self.observer {
+							self.server_data.push_str("Observing the game\n");
+							self.observer = true;
+							self.sid = None;
+							self.view_center = ds::Position::new(0.0, 0.0);
+						}
+						self.seen = soldiers.into_iter()
+							.map(|(sid, info)| (sid, info.external))
+							.collect();
+						self.update_canvas();
+						true
+					}
+					ds::ServerMsg::YouNowHaveControl(sid, info) if !self.observer => {
 						self.sid = Some(sid);
 						self.view_center = info.external.position.clone();
 						self.seen.insert(sid, info.external);
 						false
 					}
+					ds::ServerMsg::YouNowHaveControl(..) => {
+						false
+					}
+					ds::ServerMsg::PlayerList { you, players } => {
+						for (id, side) in players {
+							let ds::PlayerId(p) = id;
+							self.server_data.push_str(&format!("Player {} on side {:?}{}\n", p, side,
+											   if id == you { " (you)" } else { "" }));
+						}
+						true
+					}
+					ds::ServerMsg::Error { code, request, message } => {
+						self.server_data.push_str(&format!("Error {:?}: {}\n", code, message));
+						match request {
+							// someone else got the soldier first, try another one
+							Some(ds::GameMsg::TakeControl(_)) if self.sid.is_none() && !self.observer => {
+								self.link.send_self(Msg::SendGameMsg(ds::GameMsg::QueryStatus));
+							}
+							_ => ()
+						}
+						true
+					}
 				}
 			}
 			Msg::ReceivedError(e) => {
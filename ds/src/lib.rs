@@ -2,12 +2,17 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate rmp_serde;
+extern crate serde_json;
 
 use std::collections::HashMap;
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Hash, Deserialize, Serialize)]
 pub struct SoldierID(pub i32);
 
+// a client in a room, as listed in PlayerList
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Hash, Deserialize, Serialize)]
+pub struct PlayerId(pub u64);
+
 #[derive(PartialEq, Copy, Clone, Debug, Deserialize, Serialize)]
 pub struct Position {
 	pub x: f64,
@@ -58,26 +63,75 @@ impl Position {
 	}
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+// bumped whenever the encoding of GameMsg or ServerMsg changes. Hello and
+// Welcome are the first variants and must stay so, so that any version can
+// tell the other side it is incompatible.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Deserialize, Serialize)]
+pub enum ClientKind {
+	Web,
+	Bot,
+	Other,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum GameMsg {
-	Init(i32),           // start new game
+	// must be the first message on a connection
+	Hello {
+		protocol_version: u32,
+		client_kind: ClientKind,
+		name: String,
+	},
+	Init(Scenario),      // start new game, only allowed for the room host or an admin
 	TakeControl(SoldierID),
 	QueryStatus,
 	MoveTo(SoldierID, Position),
+	ChooseSide(Side),
+	Engage(SoldierID, SoldierID),   // fire at the target when seen
+	ListRooms,
+	CreateRoom(String),  // create a room and join it
+	JoinRoom(String),
+	LeaveRoom,
+	Authenticate(String), // admin token
+	Spectate,            // watch the whole game instead of playing, until the next game starts
+	ReleaseControl(SoldierID),            // the soldier becomes available to the side again
+	TransferControl(SoldierID, PlayerId), // hand the soldier over to a player on the same side
+	ListPlayers,
 }
 
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Scenario {
+	pub seed: u64,
+	pub soldiers_per_side: i32,
+	pub map: Option<String>,   // one of the maps configured on the server, default if not given
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Deserialize, Serialize)]
 pub enum Side {
 	Red,
 	Blue,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+// the sides deploy this far from the map center (meters), or closer on
+// small maps
+pub const DEPLOYMENT_DISTANCE: f64 = 200.0;
+
+// the x coordinate the side deploys along on a map of the given size
+pub fn deployment_line(side: Side, bounds: &Position) -> f64 {
+	let dist = DEPLOYMENT_DISTANCE.min(bounds.x * 0.4);
+	match side {
+		Side::Red  => -dist,
+		Side::Blue => dist,
+	}
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct InternalSoldierInfo {
 	pub health: i32,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct FullSoldierInfo {
 	pub internal: InternalSoldierInfo,
 	pub external: SeenSoldierInfo,
@@ -88,7 +142,7 @@ pub struct Direction(pub f64);
 
 pub const MAX_NUM_SOLDIERS: i32 = 64;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(PartialEq, Debug, Clone, Deserialize, Serialize)]
 pub struct SeenSoldierInfo {
 	pub alive: bool,
 	pub position: Position,
@@ -113,13 +167,144 @@ impl SensorUpdate {
 	pub fn add(&mut self, sid: SoldierID, info: SeenSoldierInfo) {
 		self.insense.push((sid, info))
 	}
+
+	pub fn add_lost(&mut self, sid: SoldierID) {
+		self.outsense.push(sid)
+	}
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Deserialize, Serialize)]
+pub enum TerrainKind {
+	Forest,
+	Water,
+	Building,
+	Field,
+	Urban,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Polygon {
+	pub exterior: Vec<Position>,
+	pub interiors: Vec<Vec<Position>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TerrainArea {
+	pub kind: TerrainKind,
+	pub polygons: Vec<Polygon>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MapData {
+	pub roads: Vec<Vec<Position>>,
+	pub terrain: Vec<TerrainArea>,
+	pub bounds: Position,  // size of the map, centered at the origin
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RoomInfo {
+	pub name: String,
+	pub players: i32,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum ServerMsg {
-	NewGame(Vec<SoldierID>),    // including list of available soldiers
+	// reply to a compatible Hello
+	Welcome {
+		protocol_version: u32,
+	},
+	// sent to every client in the room when the game is reset, all soldiers
+	// are released; including list of available soldiers
+	NewGame(Vec<SoldierID>),
 	AvailableSoldiers(Vec<SoldierID>),
 	YouNowHaveControl(SoldierID, FullSoldierInfo),
 	SensorInfo(HashMap<SoldierID, SensorUpdate>),
+	SideAssigned(Side),
+	StatusUpdate(SoldierID, InternalSoldierInfo),
+	ControlLost(SoldierID),
+	MapData(MapData),
+	RoomList(Vec<RoomInfo>),
+	JoinedRoom(String),
+	LeftRoom(String),
+	FullState(Vec<(SoldierID, FullSoldierInfo)>),   // all soldiers, sent to spectators every tick
+	// the players in the room that have a side, spectators are not listed
+	PlayerList {
+		you: PlayerId,
+		players: Vec<(PlayerId, Side)>,
+	},
+	// a request could not be carried out; the request is included when it was decoded
+	Error {
+		code: ErrorCode,
+		request: Option<GameMsg>,
+		message: String,
+	},
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Deserialize, Serialize)]
+pub enum ErrorCode {
+	InvalidMessage,       // could not be decoded
+	SoldierUnavailable,   // dead or controlled by another client
+	WrongSide,            // the soldier is on the other side or no side was chosen
+	NotController,        // the soldier isn't controlled by the client
+	InvalidSoldier,       // no soldier with the ID is in the game
+	InvalidPosition,      // the destination isn't on the map or can't be walked to
+	NoSuchPlayer,         // no player with the ID is playing in the room
+	NotInRoom,
+	NoSuchRoom,
+	RoomExists,
+	InvalidRoomName,
+	NotAllowed,           // only the room host or an admin may do this, or a spectator chose a side during the game
+	AuthenticationFailed,
+	InvalidScenario,      // bad number of soldiers or unknown map
+	IncompatibleVersion,  // the connection is closed after this
+	HandshakeRequired,    // something other than Hello was sent first, the connection is closed after this
+}
+
+
+// how messages are encoded on a connection: msgpack in binary frames or
+// JSON in text frames
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Encoding {
+	MsgPack,
+	Json,
 }
 
+// the first message on a connection must be a Hello with our protocol
+// version. Otherwise returns the error the connection is closed with. A
+// first message that can't be decoded most likely comes from a client
+// speaking another version.
+pub fn check_hello<E: std::fmt::Display>(msg: &Result<GameMsg, E>) -> Result<(), (ErrorCode, String)> {
+	match msg {
+		Ok(GameMsg::Hello { protocol_version, .. }) if *protocol_version == PROTOCOL_VERSION => Ok(()),
+		Ok(GameMsg::Hello { protocol_version, .. }) => {
+			Err((ErrorCode::IncompatibleVersion,
+			     format!("server speaks protocol version {}, client {}", PROTOCOL_VERSION, protocol_version)))
+		}
+		Ok(_) => Err((ErrorCode::HandshakeRequired, "the first message must be Hello".to_string())),
+		Err(e) => {
+			Err((ErrorCode::IncompatibleVersion,
+			     format!("could not decode the first message, server speaks protocol version {}: {}", PROTOCOL_VERSION, e)))
+		}
+	}
+}
+
+// encodes a message from a client, as sent in binary websocket frames
+pub fn encode_game_msg(msg: &GameMsg) -> Vec<u8> {
+	rmp_serde::to_vec(msg).unwrap()
+}
+
+// decodes a message from a client, as sent in binary websocket frames.
+// Reading from a slice checks string lengths against the input instead of
+// allocating whatever length the message claims.
+pub fn decode_game_msg(buf: &[u8]) -> Result<GameMsg, rmp_serde::decode::Error> {
+	rmp_serde::from_slice(buf)
+}
+
+// decodes a message from a client, as sent in text websocket frames
+pub fn decode_game_msg_json(text: &str) -> Result<GameMsg, serde_json::Error> {
+	serde_json::from_str(text)
+}
+
+pub fn encode_server_msg_json(msg: &ServerMsg) -> String {
+	serde_json::to_string(msg).unwrap()
+}
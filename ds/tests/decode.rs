@@ -0,0 +1,198 @@
+extern crate ds;
+extern crate serde;
+extern crate rmp_serde;
+extern crate serde_json;
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use rmp_serde::Serializer;
+
+// xorshift, so that every run feeds the same inputs
+struct Gen(u64);
+
+impl Gen {
+	fn next(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+
+	fn below(&mut self, n: usize) -> usize {
+		(self.next() % n as u64) as usize
+	}
+}
+
+fn samples() -> Vec<ds::GameMsg> {
+	vec![
+		ds::GameMsg::Hello { protocol_version: ds::PROTOCOL_VERSION, client_kind: ds::ClientKind::Bot, name: "bot".to_string() },
+		ds::GameMsg::Init(ds::Scenario { seed: 42, soldiers_per_side: 4, map: Some("town".to_string()) }),
+		ds::GameMsg::TakeControl(ds::SoldierID(3)),
+		ds::GameMsg::QueryStatus,
+		ds::GameMsg::MoveTo(ds::SoldierID(1), ds::Position::new(5.0, -2.5)),
+		ds::GameMsg::ChooseSide(ds::Side::Blue),
+		ds::GameMsg::Engage(ds::SoldierID(0), ds::SoldierID(7)),
+		ds::GameMsg::ListRooms,
+		ds::GameMsg::CreateRoom("room".to_string()),
+		ds::GameMsg::JoinRoom("room".to_string()),
+		ds::GameMsg::LeaveRoom,
+		ds::GameMsg::Authenticate("token".to_string()),
+		ds::GameMsg::Spectate,
+		ds::GameMsg::ReleaseControl(ds::SoldierID(2)),
+		ds::GameMsg::TransferControl(ds::SoldierID(2), ds::PlayerId(5)),
+		ds::GameMsg::ListPlayers,
+	]
+}
+
+#[test]
+fn decodes_client_messages() {
+	for msg in samples() {
+		let dec = ds::decode_game_msg(&ds::encode_game_msg(&msg)).unwrap();
+		assert_eq!(format!("{:?}", dec), format!("{:?}", msg));
+	}
+}
+
+// variants are encoded as [index, fields], the handshake messages must
+// keep index 0 across protocol versions
+#[test]
+fn handshake_messages_come_first() {
+	let hello = ds::encode_game_msg(&samples()[0]);
+	assert_eq!(&hello[..2], &[0x92, 0x00]);
+	let mut welcome = Vec::new();
+	ds::ServerMsg::Welcome { protocol_version: ds::PROTOCOL_VERSION }
+		.serialize(&mut Serializer::new(&mut welcome)).unwrap();
+	assert_eq!(&welcome[..2], &[0x92, 0x00]);
+}
+
+#[test]
+fn only_a_compatible_hello_passes_the_handshake() {
+	let ok: Result<ds::GameMsg, String> = Ok(samples()[0].clone());
+	assert_eq!(ds::check_hello(&ok), Ok(()));
+	let old_hello = ds::GameMsg::Hello { protocol_version: ds::PROTOCOL_VERSION - 1, client_kind: ds::ClientKind::Web, name: "web".to_string() };
+	assert_eq!(ds::check_hello(&Ok::<_, String>(old_hello)).unwrap_err().0, ds::ErrorCode::IncompatibleVersion);
+	assert_eq!(ds::check_hello(&Ok::<_, String>(ds::GameMsg::QueryStatus)).unwrap_err().0, ds::ErrorCode::HandshakeRequired);
+	// QueryStatus as sent by clients from before the handshake
+	let old = ds::decode_game_msg(&[0x92, 0x02, 0x90]);
+	assert!(old.is_err());
+	assert_eq!(ds::check_hello(&old).unwrap_err().0, ds::ErrorCode::IncompatibleVersion);
+}
+
+#[test]
+fn truncated_messages_are_errors() {
+	for msg in samples() {
+		let buf = ds::encode_game_msg(&msg);
+		for len in 0..buf.len() {
+			assert!(ds::decode_game_msg(&buf[..len]).is_err(), "{:?} cut to {} bytes", msg, len);
+		}
+	}
+}
+
+#[test]
+fn random_bytes_do_not_panic() {
+	let mut gen = Gen(0x2545_f491_4f6c_dd1d);
+	for _ in 0..20000 {
+		let len = gen.below(48);
+		let buf: Vec<u8> = (0..len).map(|_| gen.next() as u8).collect();
+		let _ = ds::decode_game_msg(&buf);
+	}
+}
+
+#[test]
+fn mutated_messages_do_not_panic() {
+	let mut gen = Gen(0x9e37_79b9_7f4a_7c15);
+	let encoded: Vec<Vec<u8>> = samples().iter().map(ds::encode_game_msg).collect();
+	for _ in 0..20000 {
+		let mut buf = encoded[gen.below(encoded.len())].clone();
+		for _ in 0..1 + gen.below(4) {
+			let pos = gen.below(buf.len() + 1);
+			match gen.below(3) {
+				0 if pos < buf.len() => buf[pos] = gen.next() as u8,
+				1 if pos < buf.len() => { buf.remove(pos); }
+				_ => buf.insert(pos, gen.next() as u8)
+			}
+		}
+		let _ = ds::decode_game_msg(&buf);
+	}
+}
+
+#[test]
+fn oversized_lengths_are_errors() {
+	// a one byte room name claiming to be 4 GB long
+	let buf = ds::encode_game_msg(&ds::GameMsg::CreateRoom("x".to_string()));
+	let pos = buf.iter().position(|b| *b == b'x').unwrap() - 1;
+	let mut bad = buf[..pos].to_vec();
+	bad.extend_from_slice(&[0xdb, 0xff, 0xff, 0xff, 0xff, b'x']);
+	assert!(ds::decode_game_msg(&bad).is_err());
+}
+
+fn seen(x: f64, side: ds::Side) -> ds::SeenSoldierInfo {
+	ds::SeenSoldierInfo {
+		alive: true,
+		position: ds::Position::new(x, -1.5),
+		direction: ds::Direction(0.5),
+		side: side,
+	}
+}
+
+#[test]
+fn decodes_client_messages_as_json() {
+	for msg in samples() {
+		let text = serde_json::to_string(&msg).unwrap();
+		let dec = ds::decode_game_msg_json(&text).unwrap();
+		assert_eq!(format!("{:?}", dec), format!("{:?}", msg));
+	}
+	// as a script would write it
+	let dec = ds::decode_game_msg_json(r#"{"MoveTo":[1,{"x":5.0,"y":-2.5}]}"#).unwrap();
+	assert_eq!(format!("{:?}", dec), format!("{:?}", ds::GameMsg::MoveTo(ds::SoldierID(1), ds::Position::new(5.0, -2.5))));
+}
+
+#[test]
+fn server_messages_survive_json() {
+	let full = ds::FullSoldierInfo {
+		internal: ds::InternalSoldierInfo { health: 80 },
+		external: seen(3.0, ds::Side::Red),
+	};
+	let msgs = vec![
+		ds::ServerMsg::Welcome { protocol_version: ds::PROTOCOL_VERSION },
+		ds::ServerMsg::NewGame(vec![ds::SoldierID(0), ds::SoldierID(1)]),
+		ds::ServerMsg::YouNowHaveControl(ds::SoldierID(0), full.clone()),
+		ds::ServerMsg::SideAssigned(ds::Side::Blue),
+		ds::ServerMsg::ControlLost(ds::SoldierID(4)),
+		ds::ServerMsg::FullState(vec![(ds::SoldierID(0), full)]),
+		ds::ServerMsg::PlayerList { you: ds::PlayerId(2), players: vec![(ds::PlayerId(2), ds::Side::Red)] },
+		ds::ServerMsg::Error {
+			code: ds::ErrorCode::InvalidPosition,
+			request: Some(ds::GameMsg::MoveTo(ds::SoldierID(1), ds::Position::new(5.0, -2.5))),
+			message: "no route".to_string(),
+		},
+	];
+	for msg in msgs {
+		let text = ds::encode_server_msg_json(&msg);
+		let dec: ds::ServerMsg = serde_json::from_str(&text).unwrap();
+		assert_eq!(format!("{:?}", dec), format!("{:?}", msg));
+	}
+}
+
+// JSON object keys are strings, the soldier IDs keying SensorInfo must
+// come back as the same IDs
+#[test]
+fn sensor_info_survives_json() {
+	let mut upd = HashMap::new();
+	let mut first = ds::SensorUpdate::new();
+	first.add(ds::SoldierID(3), seen(1.0, ds::Side::Red));
+	first.add(ds::SoldierID(12), seen(2.0, ds::Side::Blue));
+	first.add_lost(ds::SoldierID(7));
+	upd.insert(ds::SoldierID(3), first);
+	upd.insert(ds::SoldierID(40), ds::SensorUpdate::new());
+	let text = ds::encode_server_msg_json(&ds::ServerMsg::SensorInfo(upd.clone()));
+	assert!(text.contains(r#""3":"#) && text.contains(r#""40":"#), "{}", text);
+	let dec = match serde_json::from_str(&text).unwrap() {
+		ds::ServerMsg::SensorInfo(dec) => dec,
+		other => panic!("decoded as {:?}", other),
+	};
+	assert_eq!(dec.len(), upd.len());
+	for (sid, u) in upd.iter() {
+		assert_eq!(format!("{:?}", dec[sid]), format!("{:?}", u));
+	}
+}
@@ -4,4 +4,8 @@ set -e
 set -u
 
 cargo web deploy --release -p client --target=wasm32-unknown-unknown
-cargo run -p server
+if [ -f tmp/res.osm ]; then
+	cargo run -p server -- --map tmp/res.osm
+else
+	cargo run -p server
+fi
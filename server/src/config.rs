@@ -0,0 +1,162 @@
+extern crate toml;
+extern crate ds;
+extern crate sim;
+
+use std::collections::HashMap;
+use std::fs;
+use std::net::ToSocketAddrs;
+use std::path::Path;
+use std::str::FromStr;
+
+pub use sim::config::{GameConfig, MapConfig};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+	pub bind_address: String,
+	// directory the web client is served from
+	pub static_path: String,
+	// how often heartbeat pings are sent
+	pub heartbeat_interval_ms: u64,
+	// how long before lack of client response causes a timeout
+	pub client_timeout_ms: u64,
+	// clients authenticating with this token may reset any game
+	pub admin_token: Option<String>,
+	// directory to record a replay of every game to
+	pub replay_dir: Option<String>,
+}
+
+impl Default for ServerConfig {
+	fn default() -> ServerConfig {
+		ServerConfig {
+			bind_address: "0.0.0.0:8080".to_string(),
+			static_path: "target/deploy".to_string(),
+			heartbeat_interval_ms: 5000,
+			client_timeout_ms: 10000,
+			admin_token: None,
+			replay_dir: None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	pub server: ServerConfig,
+	pub game: GameConfig,
+	pub map: MapConfig,
+	// maps that can be selected when starting a game, by name
+	pub maps: HashMap<String, MapConfig>,
+	// replay file to play back instead of hosting games, command line only
+	#[serde(skip)]
+	pub replay: Option<String>,
+}
+
+pub const USAGE: &str = "Usage: server [options]
+
+Options:
+    --config <file.toml>             read the configuration from a file
+    --bind <address:port>            address to listen on
+    --static <dir>                   directory to serve the web client from
+    --update-interval <ms>           time between game updates
+    --walking-speed <m/s>            off-road walking speed
+    --road-speed <m/s>               walking speed on roads
+    --soldiers <n>                   number of soldiers per side
+    --heartbeat-interval <ms>        time between websocket pings
+    --client-timeout <ms>            time before an unresponsive client is dropped
+    --admin-token <token>            token that allows resetting any game
+    --replay-dir <dir>               record a replay of every game to this directory
+    --replay <file.replay>           play back a recorded game to connecting clients
+    --map <file.osm>                 map to use, a flat map is used if not given
+    --bbox <minlat,minlon,maxlat,maxlon>
+                                     use only this area of the map
+
+Command line options override the configuration file.";
+
+fn parse<T: FromStr>(opt: &str, val: String) -> Result<T, String> {
+	val.parse().map_err(|_| format!("invalid value for {}: {}", opt, val))
+}
+
+impl Config {
+	pub fn from_file(path: &str) -> Result<Config, String> {
+		let contents = fs::read_to_string(path)
+			.map_err(|e| format!("could not read {}: {}", path, e))?;
+		toml::from_str(&contents).map_err(|e| format!("could not parse {}: {}", path, e))
+	}
+
+	pub fn from_args<I: Iterator<Item = String>>(args: I) -> Result<Config, String> {
+		let args: Vec<String> = args.collect();
+		let config_file = args.iter()
+			.position(|a| a == "--config")
+			.map(|i| args.get(i + 1).cloned().ok_or("missing value for --config".to_string()));
+		let mut config = match config_file {
+			Some(path) => Config::from_file(&path?)?,
+			None => Config::default(),
+		};
+
+		let mut it = args.into_iter();
+		while let Some(opt) = it.next() {
+			let val = it.next().ok_or(format!("missing value for {}", opt))?;
+			match opt.as_str() {
+				"--config"             => (),
+				"--bind"               => config.server.bind_address = val,
+				"--static"             => config.server.static_path = val,
+				"--heartbeat-interval" => config.server.heartbeat_interval_ms = parse(&opt, val)?,
+				"--client-timeout"     => config.server.client_timeout_ms = parse(&opt, val)?,
+				"--admin-token"        => config.server.admin_token = Some(val),
+				"--replay-dir"         => config.server.replay_dir = Some(val),
+				"--replay"             => config.replay = Some(val),
+				"--update-interval"    => config.game.update_interval_ms = parse(&opt, val)?,
+				"--walking-speed"      => config.game.walking_speed = parse(&opt, val)?,
+				"--road-speed"         => config.game.road_speed = parse(&opt, val)?,
+				"--soldiers"           => config.game.soldiers_per_side = parse(&opt, val)?,
+				"--map"                => config.map.path = Some(val),
+				"--bbox"               => config.map.bbox = Some(val.parse()?),
+				_                      => return Err(format!("unknown option {}", opt))
+			}
+		}
+		config.validate()?;
+		Ok(config)
+	}
+
+	pub fn validate(&self) -> Result<(), String> {
+		let s = &self.server;
+		let g = &self.game;
+		match s.bind_address.to_socket_addrs() {
+			Ok(_) => (),
+			Err(e) => return Err(format!("invalid bind address {}: {}", s.bind_address, e))
+		}
+		if !Path::new(&s.static_path).is_dir() {
+			return Err(format!("static path {} is not a directory", s.static_path));
+		}
+		if s.heartbeat_interval_ms == 0 {
+			return Err("heartbeat interval must be positive".to_string());
+		}
+		if s.client_timeout_ms <= s.heartbeat_interval_ms {
+			return Err("client timeout must be longer than the heartbeat interval".to_string());
+		}
+		if g.update_interval_ms == 0 {
+			return Err("update interval must be positive".to_string());
+		}
+		if !(g.walking_speed > 0.0 && g.walking_speed.is_finite()) ||
+			!(g.road_speed > 0.0 && g.road_speed.is_finite()) {
+			return Err("speeds must be positive".to_string());
+		}
+		if g.soldiers_per_side < 1 || g.soldiers_per_side * 2 > ds::MAX_NUM_SOLDIERS {
+			return Err(format!("soldiers per side must be between 1 and {}", ds::MAX_NUM_SOLDIERS / 2));
+		}
+		if s.admin_token.as_ref().map(|t| t.is_empty()) == Some(true) {
+			return Err("admin token must not be empty".to_string());
+		}
+		if let Some(ref dir) = s.replay_dir {
+			if !Path::new(dir).is_dir() {
+				return Err(format!("replay directory {} is not a directory", dir));
+			}
+		}
+		self.map.validate()?;
+		for (name, map) in self.maps.iter() {
+			map.validate().map_err(|e| format!("map {}: {}", name, e))?;
+		}
+		Ok(())
+	}
+}
@@ -1,41 +1,100 @@
 extern crate actix;
+#[macro_use]
+extern crate serde_derive;
 extern crate actix_web;
 extern crate env_logger;
 extern crate ds;
+extern crate sim;
 extern crate rmp_serde;
 extern crate serde;
 
+use std::sync::Arc;
+use std::time::Duration;
+
 use actix::prelude::*;
 use actix_web::{
 	fs, http, middleware, server, App,
 };
 
 mod websocket;
-mod osm;
-mod game;
+mod config;
+mod replay;
+mod room;
 mod serv;
 
 use crate::websocket::*;
 use crate::serv::*;
 
+// what the websocket connections talk to
+#[derive(Clone)]
+enum Host {
+	Lobby(Addr<ChatServer>),
+	Replay(Addr<replay::ReplayPlayer>),
+}
+
+impl Host {
+	// made for each server thread, a Recipient can't be shared between them
+	fn recipient(&self) -> Recipient<WebSocketMsg> {
+		match self {
+			Host::Lobby(addr) => addr.clone().recipient(),
+			Host::Replay(addr) => addr.clone().recipient(),
+		}
+	}
+}
+
 fn main() {
 	let sys = actix::System::new("websocket-example");
-	osm::run_osm();
+	let conf = match config::Config::from_args(std::env::args().skip(1)) {
+		Ok(c) => c,
+		Err(e) => {
+			println!("{}\n{}", e, config::USAGE);
+			std::process::exit(1);
+		}
+	};
+	let maps = match sim::osm::load_maps(&conf.map, &conf.maps) {
+		Ok(m) => Arc::new(m),
+		Err(e) => {
+			println!("Error loading map: {}", e);
+			std::process::exit(1);
+		}
+	};
 	::std::env::set_var("RUST_LOG", "actix_web=info");
 	env_logger::init();
-	let addr = "0.0.0.0:8080";
+	let addr = conf.server.bind_address.clone();
 	println!("Starting server at {}", addr);
-	let chatserver = Arbiter::start(|_| ChatServer::default());
+	let host = match conf.replay {
+		Some(ref path) => {
+			let player = match sim::replay::Replay::load(path).and_then(|r| replay::ReplayPlayer::new(r, &maps)) {
+				Ok(p) => p,
+				Err(e) => {
+					println!("Error loading replay: {}", e);
+					std::process::exit(1);
+				}
+			};
+			Host::Replay(Arbiter::start(move |_| player))
+		}
+		None => {
+			let game_config = conf.game.clone();
+			let admin_token = conf.server.admin_token.clone();
+			let replay_dir = conf.server.replay_dir.clone();
+			Host::Lobby(Arbiter::start(move |_| ChatServer::new(maps, game_config, admin_token, replay_dir)))
+		}
+	};
+	let server_config = conf.server.clone();
 	server::new(
 		move || {
-			let state = chatserver.clone().recipient();
+			let state = WsState {
+				server: host.recipient(),
+				heartbeat_interval: Duration::from_millis(server_config.heartbeat_interval_ms),
+				client_timeout: Duration::from_millis(server_config.client_timeout_ms),
+			};
 
 			App::with_state(state)
 				.middleware(middleware::Logger::default())
 				.resource("/ws/", |r| r.method(http::Method::GET).f(ws_index))
 				.handler(
 					"/",
-					fs::StaticFiles::new("target/deploy").unwrap().index_file("index.html"))}
+					fs::StaticFiles::new(&server_config.static_path).unwrap().index_file("index.html"))}
 		   )
 		.bind(addr).unwrap()
 		.start();
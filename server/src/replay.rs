@@ -0,0 +1,131 @@
+extern crate actix;
+extern crate ds;
+extern crate sim;
+
+use std::collections::HashSet;
+
+use actix::prelude::*;
+
+use sim::GameState;
+use sim::osm::Maps;
+use sim::replay::Replay;
+
+use crate::websocket::*;
+
+// plays a replay back in real time. Connecting clients spectate: they get
+// the full game state every tick as well as the messages the recorded
+// clients got, and nothing they send has an effect.
+pub struct ReplayPlayer {
+	replay: Replay,
+	game: GameState,
+	next_event: usize,
+	next_checkpoint: usize,
+	diverged: bool,
+	finished: bool,
+	spectators: HashSet<Addr<MyWebSocket>>,
+}
+
+#[derive(Message)]
+struct StepMessage;
+
+impl ReplayPlayer {
+	pub fn new(replay: Replay, maps: &Maps) -> Result<ReplayPlayer, String> {
+		let game = replay.start(maps)?;
+		Ok(ReplayPlayer {
+			replay: replay,
+			game: game,
+			next_event: 0,
+			next_checkpoint: 0,
+			diverged: false,
+			finished: false,
+			spectators: HashSet::new(),
+		})
+	}
+
+	// the game is checked against the checkpoints as it goes
+	fn check(&mut self) {
+		while self.next_checkpoint < self.replay.checkpoints.len() &&
+			self.replay.checkpoints[self.next_checkpoint].0 <= self.game.ticks() {
+			let (ticks, checksum) = self.replay.checkpoints[self.next_checkpoint];
+			if ticks == self.game.ticks() && checksum != self.game.checksum() && !self.diverged {
+				println!("Replay diverged from the recording at tick {}", ticks);
+				self.diverged = true;
+			}
+			self.next_checkpoint += 1;
+		}
+	}
+
+	// events are applied at the same tick as they were recorded at, after
+	// the checkpoint of that tick
+	fn step(&mut self) {
+		self.check();
+		while self.next_event < self.replay.events.len() &&
+			self.replay.events[self.next_event].tick <= self.game.ticks() {
+			let ev = &self.replay.events[self.next_event];
+			ev.event.clone().apply(ev.client, &mut self.game);
+			self.next_event += 1;
+		}
+		if self.game.ticks() >= self.replay.ticks() {
+			self.finished = true;
+			if self.diverged {
+				println!("Replay finished after {} ticks but diverged from the recording", self.game.ticks());
+			} else {
+				println!("Replay finished after {} ticks", self.game.ticks());
+			}
+		} else {
+			self.game.tick();
+		}
+		// recorded spectators' states would be duplicates
+		let mut msgs: Vec<ds::ServerMsg> = self.game.take_messages().into_iter()
+			.map(|(_, m)| m)
+			.filter(|m| match m { ds::ServerMsg::FullState(_) => false, _ => true })
+			.collect();
+		msgs.push(ds::ServerMsg::FullState(self.game.full_state()));
+		for msg in msgs {
+			for s in self.spectators.iter() {
+				s.do_send(ServerMsg { msg: msg.clone() });
+			}
+		}
+	}
+}
+
+impl Actor for ReplayPlayer {
+	type Context = Context<Self>;
+
+	fn started(&mut self, ctx: &mut Self::Context) {
+		println!("Playing back a replay of {} ticks", self.replay.ticks());
+		ctx.run_interval(self.game.timestep(), |_act, ct| {
+			ct.address().do_send(StepMessage);
+		});
+	}
+}
+
+impl Handler<StepMessage> for ReplayPlayer {
+	type Result = ();
+
+	fn handle(&mut self, _msg: StepMessage, _ctx: &mut Context<Self>) -> Self::Result {
+		if !self.finished {
+			self.step();
+		}
+	}
+}
+
+impl Handler<WebSocketMsg> for ReplayPlayer {
+	type Result = ();
+
+	fn handle(&mut self, msg: WebSocketMsg, _: &mut Context<Self>) -> Self::Result {
+		match msg {
+			WebSocketMsg::Connected(addr) => {
+				addr.do_send(ServerMsg { msg: ds::ServerMsg::MapData(self.game.map_data().clone()) });
+				self.spectators.insert(addr);
+			}
+			WebSocketMsg::Disconnected(addr) => {
+				self.spectators.remove(&addr);
+			}
+			WebSocketMsg::IncomingData(addr, gmsg) => {
+				addr.do_send(ServerMsg::error(ds::ErrorCode::NotAllowed, gmsg,
+					"a replay is playing, requests have no effect".to_string()));
+			}
+		}
+	}
+}
@@ -0,0 +1,238 @@
+extern crate actix;
+extern crate rand;
+extern crate ds;
+extern crate sim;
+
+use std::time::{Duration, Instant};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use actix::prelude::*;
+
+use sim::{ClientId, GameState};
+use sim::osm::Maps;
+use sim::config::GameConfig;
+use sim::replay::{ClientEvent, Recorder, ReplayHeader};
+
+use crate::websocket::*;
+
+// at most this many ticks are run to catch up when updates are late
+const MAX_CATCHUP_TICKS: u32 = 5;
+
+// a game room with its own game state and update loop
+pub struct Room {
+	name: String,
+	clients: HashMap<ClientId, Addr<MyWebSocket>>,
+	game: GameState,
+	update_interval: Duration,
+	// real time not yet simulated
+	lag: Duration,
+	last_update: Instant,
+	maps: Arc<Maps>,
+	config: GameConfig,
+	// replay of the current game, if recording
+	recording: Option<Recorder>,
+	replay_dir: Option<String>,
+}
+
+#[derive(Message)]
+pub enum RoomMsg {
+	Join(ClientId, Addr<MyWebSocket>),
+	Leave(ClientId),
+	Game(ClientId, ds::GameMsg),
+	Close,
+}
+
+#[derive(Message)]
+struct UpdateMessage;
+
+impl Handler<UpdateMessage> for Room {
+	type Result = ();
+
+	// the game runs in fixed timesteps, as many as fit in the elapsed time
+	fn handle(&mut self, _msg: UpdateMessage, _ctx: &mut Context<Self>) -> Self::Result {
+		let now = Instant::now();
+		self.lag += now - self.last_update;
+		self.last_update = now;
+		let step = self.game.timestep();
+		let mut ticks = 0;
+		while self.lag >= step {
+			self.lag -= step;
+			ticks += 1;
+			if ticks > MAX_CATCHUP_TICKS {
+				self.lag = Duration::from_secs(0);
+				break;
+			}
+			self.game.tick();
+			let game = &self.game;
+			let res = self.recording.as_mut().map_or(Ok(()), |r| r.ticked(game));
+			self.recorded(res);
+			self.flush();
+		}
+	}
+}
+
+impl Room {
+	// the first game is played on the default map with a random seed
+	pub fn new(name: String, maps: Arc<Maps>, config: GameConfig, replay_dir: Option<String>) -> Room {
+		let map = maps.get(None).unwrap();
+		let seed = rand::random();
+		let mut room = Room {
+			name: name,
+			clients: HashMap::new(),
+			update_interval: config.update_interval(),
+			lag: Duration::from_secs(0),
+			last_update: Instant::now(),
+			game: GameState::new(map, config.clone(), seed),
+			maps: maps,
+			config: config.clone(),
+			recording: None,
+			replay_dir: replay_dir,
+		};
+		room.start_recording(config.clone(), ds::Scenario {
+			seed: seed,
+			soldiers_per_side: config.soldiers_per_side,
+			map: None,
+		});
+		room
+	}
+
+	// sends the messages the game has for the clients
+	fn flush(&mut self) {
+		for (client, msg) in self.game.take_messages() {
+			match self.clients.get(&client) {
+				Some(addr) => addr.do_send(ServerMsg { msg: msg }),
+				None => ()
+			}
+		}
+	}
+
+	fn client_event(&mut self, client: ClientId, event: ClientEvent) {
+		let tick = self.game.ticks();
+		let res = self.recording.as_mut().map_or(Ok(()), |r| r.record(tick, client, event.clone()));
+		self.recorded(res);
+		event.apply(client, &mut self.game);
+		self.flush();
+	}
+
+	fn start_recording(&mut self, config: GameConfig, scenario: ds::Scenario) {
+		let dir = match self.replay_dir {
+			Some(ref d) => d.clone(),
+			None => return
+		};
+		let header = ReplayHeader {
+			config: config,
+			scenario: scenario,
+			clients: self.game.clients(),
+			spectators: self.game.spectators(),
+		};
+		match Recorder::create(&dir, &self.name, &header) {
+			Ok(r) => self.recording = Some(r),
+			Err(e) => println!("room {}: not recording: {}", self.name, e)
+		}
+	}
+
+	// recording stops if the replay can't be written
+	fn recorded(&mut self, res: Result<(), String>) {
+		if let Err(e) = res {
+			println!("room {}: stopped recording: {}", self.name, e);
+			self.recording = None;
+		}
+	}
+
+	fn finish_recording(&mut self) {
+		match self.recording.take().map(|r| r.finish(&self.game)) {
+			Some(Ok(Some(path))) => println!("room {}: replay saved to {}", self.name, path),
+			Some(Err(e)) => println!("room {}: could not save replay: {}", self.name, e),
+			_ => ()
+		}
+	}
+
+	fn send_error(&self, client: ClientId, code: ds::ErrorCode, request: ds::GameMsg, message: String) {
+		match self.clients.get(&client) {
+			Some(addr) => addr.do_send(ServerMsg::error(code, request, message)),
+			None => ()
+		}
+	}
+
+	fn start_scenario(&mut self, client: ClientId, scenario: ds::Scenario) {
+		if scenario.soldiers_per_side < 1 || scenario.soldiers_per_side > ds::MAX_NUM_SOLDIERS / 2 {
+			self.send_error(client, ds::ErrorCode::InvalidScenario, ds::GameMsg::Init(scenario.clone()),
+					format!("soldiers per side must be between 1 and {}", ds::MAX_NUM_SOLDIERS / 2));
+			return;
+		}
+		let map = match self.maps.get(scenario.map.as_ref().map(|m| m.as_str())) {
+			Some(m) => m,
+			None => {
+				self.send_error(client, ds::ErrorCode::InvalidScenario, ds::GameMsg::Init(scenario.clone()),
+						format!("unknown map {}", scenario.map.clone().unwrap_or_default()));
+				return;
+			}
+		};
+		let config = GameConfig {
+			soldiers_per_side: scenario.soldiers_per_side,
+			..self.config.clone()
+		};
+		println!("room {}: game ended after {} ticks, checksum {:016x}",
+			 self.name, self.game.ticks(), self.game.checksum());
+		self.finish_recording();
+		println!("room {}: new game with seed {}", self.name, scenario.seed);
+		self.game.restart(map, config.clone(), scenario.seed);
+		self.start_recording(config, scenario);
+		// everyone lost their soldiers, tell all clients about the new game
+		for (client, addr) in self.clients.iter() {
+			let val = ds::ServerMsg::NewGame(self.game.available_soldiers(*client));
+			addr.do_send(ServerMsg {
+				msg: val
+			});
+			self.game.send_map(*client);
+		}
+		self.flush();
+	}
+
+	fn update(&self, ctx: &mut <Self as Actor>::Context) {
+		ctx.run_interval(self.update_interval, |_act, ct| {
+			ct.address().do_send(UpdateMessage);
+		});
+	}
+}
+
+impl Actor for Room {
+	type Context = Context<Self>;
+
+	fn started(&mut self, ctx: &mut Self::Context) {
+		println!("room {} opened", self.name);
+		self.update(ctx);
+	}
+
+	fn stopped(&mut self, _ctx: &mut Self::Context) {
+		self.finish_recording();
+		println!("room {} closed", self.name);
+	}
+}
+
+impl Handler<RoomMsg> for Room {
+	type Result = ();
+
+	fn handle(&mut self, msg: RoomMsg, ctx: &mut Context<Self>) -> Self::Result {
+		match msg {
+			RoomMsg::Join(client, addr) => {
+				self.clients.insert(client, addr);
+				self.client_event(client, ClientEvent::Connected);
+			}
+			RoomMsg::Leave(client) => {
+				self.client_event(client, ClientEvent::Disconnected);
+				self.clients.remove(&client);
+			}
+			RoomMsg::Game(client, ds::GameMsg::Init(scenario)) => {
+				self.start_scenario(client, scenario);
+			}
+			RoomMsg::Game(client, gmsg) => {
+				self.client_event(client, ClientEvent::Msg(gmsg));
+			}
+			RoomMsg::Close => {
+				ctx.stop();
+			}
+		}
+	}
+}
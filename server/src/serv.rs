@@ -2,58 +2,130 @@ extern crate actix;
 extern crate actix_web;
 extern crate env_logger;
 extern crate ds;
+extern crate sim;
 
-use std::time::{Duration};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 use actix::prelude::*;
 
 use crate::websocket::*;
-use crate::game::*;
+use crate::room::*;
+use sim::ClientId;
+use sim::osm::Maps;
+use sim::config::GameConfig;
+
+// room new clients are placed in, always open
+pub const DEFAULT_ROOM: &str = "default";
+const MAX_ROOM_NAME_LEN: usize = 32;
+
+struct RoomEntry {
+	addr: Addr<Room>,
+	// in the order of joining, the first one is the host
+	members: Vec<Addr<MyWebSocket>>,
+}
 
+// the lobby: keeps track of the game rooms and forwards game messages
+// to the room each client is in
 pub struct ChatServer {
-	clients: HashSet<Addr<MyWebSocket>>,
-	game: GameState,
+	rooms: HashMap<String, RoomEntry>,
+	client_rooms: HashMap<Addr<MyWebSocket>, String>,
+	client_ids: HashMap<Addr<MyWebSocket>, ClientId>,
+	next_client_id: u64,
+	// clients that have authenticated with the admin token
+	admins: HashSet<Addr<MyWebSocket>>,
+	admin_token: Option<String>,
+	maps: Arc<Maps>,
+	config: GameConfig,
+	replay_dir: Option<String>,
 }
 
-#[derive(Message)]
-struct UpdateMessage;
+impl ChatServer {
+	pub fn new(maps: Arc<Maps>, config: GameConfig, admin_token: Option<String>,
+		   replay_dir: Option<String>) -> ChatServer {
+		ChatServer {
+			rooms: HashMap::new(),
+			client_rooms: HashMap::new(),
+			client_ids: HashMap::new(),
+			next_client_id: 0,
+			admins: HashSet::new(),
+			admin_token: admin_token,
+			maps: maps,
+			config: config,
+			replay_dir: replay_dir,
+		}
+	}
 
-const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+	fn client_id(&self, addr: &Addr<MyWebSocket>) -> ClientId {
+		self.client_ids[addr]
+	}
 
-impl Handler<UpdateMessage> for ChatServer {
-	type Result = ();
+	fn open_room(&mut self, name: &str) {
+		let room = Room::new(name.to_string(), self.maps.clone(), self.config.clone(),
+				     self.replay_dir.clone());
+		self.rooms.insert(name.to_string(), RoomEntry {
+			addr: room.start(),
+			members: vec![],
+		});
+	}
 
-	fn handle(&mut self, _msg: UpdateMessage, _ctx: &mut Context<Self>) -> Self::Result {
-		let msgs = self.game.tick(UPDATE_INTERVAL);
-		for (recp, msg) in msgs {
-			recp.do_send(ServerMsg { msg: msg });
+	fn join_room(&mut self, addr: Addr<MyWebSocket>, name: &str) {
+		if !self.rooms.contains_key(name) {
+			println!("no room named {}", name);
+			return;
+		}
+		self.leave_room(&addr);
+		if let Some(room) = self.rooms.get_mut(name) {
+			room.members.push(addr.clone());
+			addr.do_send(ServerMsg { msg: ds::ServerMsg::JoinedRoom(name.to_string()) });
+			room.addr.do_send(RoomMsg::Join(self.client_ids[&addr], addr.clone()));
+			self.client_rooms.insert(addr, name.to_string());
 		}
 	}
-}
 
-impl ChatServer {
-	fn update(&self, ctx: &mut <Self as Actor>::Context) {
-		ctx.run_interval(UPDATE_INTERVAL, |_act, ct| {
-			ct.address().do_send(UpdateMessage);
-		});
+	// rooms other than the default one are closed once everyone has left
+	fn leave_room(&mut self, addr: &Addr<MyWebSocket>) -> Option<String> {
+		let name = self.client_rooms.remove(addr)?;
+		let client = self.client_id(addr);
+		let empty = match self.rooms.get_mut(&name) {
+			Some(room) => {
+				room.members.retain(|m| m != addr);
+				room.addr.do_send(RoomMsg::Leave(client));
+				room.members.is_empty()
+			}
+			None => false
+		};
+		if empty && name != DEFAULT_ROOM {
+			if let Some(room) = self.rooms.remove(&name) {
+				room.addr.do_send(RoomMsg::Close);
+			}
+		}
+		Some(name)
 	}
-}
 
-impl Default for ChatServer {
-	fn default() -> ChatServer {
-		ChatServer {
-			clients: HashSet::new(),
-			game: GameState::new(),
-		}
+	// only the room host and admins may reset the game
+	fn may_reset(&self, addr: &Addr<MyWebSocket>) -> bool {
+		self.admins.contains(addr) ||
+			self.client_rooms.get(addr)
+				.and_then(|name| self.rooms.get(name))
+				.and_then(|room| room.members.first()) == Some(addr)
+	}
+
+	fn room_list(&self) -> Vec<ds::RoomInfo> {
+		let mut list: Vec<ds::RoomInfo> = self.rooms.iter().map(|(name, room)| ds::RoomInfo {
+			name: name.clone(),
+			players: room.members.len() as i32,
+		}).collect();
+		list.sort_by(|a, b| a.name.cmp(&b.name));
+		list
 	}
 }
 
 impl Actor for ChatServer {
 	type Context = Context<Self>;
 
-	fn started(&mut self, ctx: &mut Self::Context) {
-		self.update(ctx);
+	fn started(&mut self, _ctx: &mut Self::Context) {
+		self.open_room(DEFAULT_ROOM);
 	}
 }
 
@@ -63,24 +135,77 @@ impl Handler<WebSocketMsg> for ChatServer {
 	fn handle(&mut self, msg: WebSocketMsg, _: &mut Context<Self>) -> Self::Result {
 		match msg {
 			WebSocketMsg::Connected(addr) => {
-				self.clients.insert(addr);
+				self.client_ids.insert(addr.clone(), ClientId(self.next_client_id));
+				self.next_client_id += 1;
+				self.join_room(addr, DEFAULT_ROOM);
 			}
 			WebSocketMsg::Disconnected(addr) => {
+				if !self.client_ids.contains_key(&addr) {
+					return;
+				}
 				println!("client disconnected");
-				self.clients.remove(&addr);
-				self.game.client_disconnected(addr);
+				self.leave_room(&addr);
+				self.admins.remove(&addr);
+				self.client_ids.remove(&addr);
 			}
 			WebSocketMsg::IncomingData(addr, gmsg) => {
-				if self.game.game_msg(&addr, gmsg) {
-					self.game = GameState::new();
-					let val = ds::ServerMsg::NewGame(self.game.available_soldiers());
-					addr.do_send(ServerMsg {
-						msg: val
-					});
+				if !self.client_ids.contains_key(&addr) {
+					return;
+				}
+				match gmsg.clone() {
+					ds::GameMsg::ListRooms => {
+						addr.do_send(ServerMsg { msg: ds::ServerMsg::RoomList(self.room_list()) });
+					}
+					ds::GameMsg::CreateRoom(name) => {
+						if name.is_empty() || name.len() > MAX_ROOM_NAME_LEN {
+							addr.do_send(ServerMsg::error(ds::ErrorCode::InvalidRoomName, gmsg,
+								format!("room names must be 1 to {} bytes long", MAX_ROOM_NAME_LEN)));
+						} else if self.rooms.contains_key(&name) {
+							addr.do_send(ServerMsg::error(ds::ErrorCode::RoomExists, gmsg,
+								format!("room {} already exists", name)));
+						} else {
+							self.open_room(&name);
+							self.join_room(addr, &name);
+						}
+					}
+					ds::GameMsg::JoinRoom(name) => {
+						if !self.rooms.contains_key(&name) {
+							addr.do_send(ServerMsg::error(ds::ErrorCode::NoSuchRoom, gmsg,
+								format!("no room named {}", name)));
+						} else if self.client_rooms.get(&addr) != Some(&name) {
+							self.join_room(addr, &name);
+						}
+					}
+					ds::GameMsg::Authenticate(token) => {
+						if self.admin_token.is_some() && self.admin_token.as_ref() == Some(&token) {
+							println!("client authenticated as admin");
+							self.admins.insert(addr);
+						} else {
+							println!("client failed to authenticate");
+							addr.do_send(ServerMsg::error(ds::ErrorCode::AuthenticationFailed, gmsg,
+								"invalid admin token".to_string()));
+						}
+					}
+					ds::GameMsg::Init(_) if !self.may_reset(&addr) => {
+						addr.do_send(ServerMsg::error(ds::ErrorCode::NotAllowed, gmsg,
+							"only the room host or an admin may start a new game".to_string()));
+					}
+					ds::GameMsg::LeaveRoom => {
+						match self.leave_room(&addr) {
+							Some(name) => addr.do_send(ServerMsg { msg: ds::ServerMsg::LeftRoom(name) }),
+							None => addr.do_send(ServerMsg::error(ds::ErrorCode::NotInRoom, gmsg,
+								"you are not in a room".to_string()))
+						}
+					}
+					_ => {
+						match self.client_rooms.get(&addr).and_then(|name| self.rooms.get(name)) {
+							Some(room) => room.addr.do_send(RoomMsg::Game(self.client_id(&addr), gmsg)),
+							None => addr.do_send(ServerMsg::error(ds::ErrorCode::NotInRoom, gmsg,
+								"join a room first".to_string()))
+						}
+					}
 				}
 			}
 		}
 	}
 }
-
-
@@ -10,59 +10,68 @@ use actix_web::{
 	ws, Error, HttpRequest, HttpResponse,
 };
 
-use serde::{Deserialize, Serialize};
-use rmp_serde::{Deserializer, Serializer};
-
-/// How often heartbeat pings are sent
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
-/// How long before lack of client response causes a timeout
-const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+use serde::Serialize;
+use rmp_serde::Serializer;
+
+/// Application state shared by the websocket connections
+pub struct WsState {
+	pub server: Recipient<WebSocketMsg>,
+	/// How often heartbeat pings are sent
+	pub heartbeat_interval: Duration,
+	/// How long before lack of client response causes a timeout
+	pub client_timeout: Duration,
+}
 
 /// do websocket handshake and start `MyWebSocket` actor
-pub fn ws_index(r: &HttpRequest<Recipient<WebSocketMsg>>) -> Result<HttpResponse, Error> {
+pub fn ws_index(r: &HttpRequest<WsState>) -> Result<HttpResponse, Error> {
 	ws::start(r, MyWebSocket::new())
 }
 
 /// websocket connection is long running connection, it easier
 /// to handle with an actor
 pub struct MyWebSocket {
-	/// Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT),
+	/// Client must send ping at least once per client timeout,
 	/// otherwise we drop connection.
 	hb: Instant,
+	/// Whether the client has said a compatible Hello; nothing else is
+	/// accepted before it
+	greeted: bool,
+	/// Replies are encoded the way the client's Hello was
+	encoding: ds::Encoding,
 }
 
 #[allow(unused_must_use)]
 impl Actor for MyWebSocket {
-	type Context = ws::WebsocketContext<Self, Recipient<WebSocketMsg>>;
+	type Context = ws::WebsocketContext<Self, WsState>;
 
 	/// Method is called on actor start. We start the heartbeat process here.
+	/// The server learns about the client once it has said hello.
 	fn started(&mut self, ctx: &mut Self::Context) {
 		self.hb(ctx);
-		ctx.state().do_send(WebSocketMsg::Connected(ctx.address()));
 	}
 
 	fn stopped(&mut self, ctx: &mut Self::Context) {
-		ctx.state().do_send(WebSocketMsg::Disconnected(ctx.address()));
+		ctx.state().server.do_send(WebSocketMsg::Disconnected(ctx.address()));
 	}
 }
 
 #[allow(unused_must_use)]
 impl MyWebSocket {
 	fn new() -> Self {
-		Self { hb: Instant::now() }
+		Self { hb: Instant::now(), greeted: false, encoding: ds::Encoding::MsgPack }
 	}
 
 	/// helper method that sends ping to client every second.
 	///
 	/// also this method checks heartbeats from client
 	fn hb(&self, ctx: &mut <Self as Actor>::Context) {
-		ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+		ctx.run_interval(ctx.state().heartbeat_interval, |act, ctx| {
 			// check client heartbeats
-			if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
+			if Instant::now().duration_since(act.hb) > ctx.state().client_timeout {
 				// heartbeat timed out
 				println!("Websocket Client heartbeat failed, disconnecting!");
 
-				ctx.state().do_send(WebSocketMsg::Disconnected(ctx.address()));
+				ctx.state().server.do_send(WebSocketMsg::Disconnected(ctx.address()));
 
 				// stop actor
 				ctx.stop();
@@ -74,15 +83,86 @@ impl MyWebSocket {
 			ctx.ping("");
 		});
 	}
+
+	/// the first message must be a Hello with our protocol version,
+	/// otherwise the connection is closed
+	fn handshake(&mut self, msg: Result<ds::GameMsg, String>, ctx: &mut <Self as Actor>::Context) {
+		let (code, reason) = match ds::check_hello(&msg) {
+			Ok(()) => {
+				if let Ok(ds::GameMsg::Hello { client_kind, ref name, .. }) = msg {
+					println!("{:?} client {} connected", client_kind, name);
+				}
+				self.greeted = true;
+				self.send(&ds::ServerMsg::Welcome { protocol_version: ds::PROTOCOL_VERSION }, ctx);
+				ctx.state().server.do_send(WebSocketMsg::Connected(ctx.address()));
+				return;
+			}
+			Err(e) => e
+		};
+		println!("rejecting client: {}", reason);
+		self.send(&ds::ServerMsg::Error {
+			code: code,
+			request: msg.ok(),
+			message: reason.clone(),
+		}, ctx);
+		ctx.close(Some(ws::CloseReason {
+			code: ws::CloseCode::Protocol,
+			description: Some(reason),
+		}));
+	}
+
+	fn received(&mut self, msg: Result<ds::GameMsg, String>, encoding: ds::Encoding,
+		    ctx: &mut <Self as Actor>::Context) {
+		if !self.greeted {
+			self.encoding = encoding;
+			self.handshake(msg, ctx);
+			return;
+		}
+		match msg {
+			Ok(gmsg) => {
+				println!("Got gmsg {:?}", gmsg);
+				match gmsg {
+					ds::GameMsg::Hello { .. } => {
+						self.send(&ds::ServerMsg::Error {
+							code: ds::ErrorCode::NotAllowed,
+							request: Some(gmsg),
+							message: "already greeted".to_string(),
+						}, ctx);
+					}
+					_ => {
+						ctx.state().server.do_send(WebSocketMsg::IncomingData(ctx.address(), gmsg));
+					}
+				}
+			}
+			Err(e) => {
+				self.send(&ds::ServerMsg::Error {
+					code: ds::ErrorCode::InvalidMessage,
+					request: None,
+					message: format!("could not decode message: {}", e),
+				}, ctx);
+			}
+		}
+	}
+
+	fn send(&self, msg: &ds::ServerMsg, ctx: &mut <Self as Actor>::Context) {
+		match self.encoding {
+			ds::Encoding::MsgPack => {
+				let mut buf = Vec::new();
+				msg.serialize(&mut Serializer::new(&mut buf)).unwrap();
+				ctx.binary(buf);
+			}
+			ds::Encoding::Json => {
+				ctx.text(ds::encode_server_msg_json(msg));
+			}
+		}
+	}
 }
 
 impl Handler<ServerMsg> for MyWebSocket {
 	type Result = ();
 
 	fn handle(&mut self, msg: ServerMsg, ctx: &mut Self::Context) {
-		let mut buf = Vec::new();
-		msg.msg.serialize(&mut Serializer::new(&mut buf)).unwrap();
-		ctx.binary(buf);
+		self.send(&msg.msg, ctx);
 	}
 }
 
@@ -98,25 +178,16 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for MyWebSocket {
 			ws::Message::Pong(_) => {
 				self.hb = Instant::now();
 			}
-			ws::Message::Text(_) => {
-				()
+			ws::Message::Text(text) => {
+				let msg = ds::decode_game_msg_json(&text).map_err(|e| e.to_string());
+				self.received(msg, ds::Encoding::Json, ctx);
 			}
 			ws::Message::Binary(mut bin) => {
-				let inp = &bin.take()[..];
-				let mut de = Deserializer::new(inp);
-				let msg: Result<ds::GameMsg, rmp_serde::decode::Error> = Deserialize::deserialize(&mut de);
-				match msg {
-					Ok(gmsg) => {
-						println!("Got gmsg {:?}", gmsg);
-						ctx.state().do_send(WebSocketMsg::IncomingData(ctx.address(), gmsg));
-					}
-					Err(e) => {
-						println!("Error: {:?}\n", e);
-					}
-				}
+				let msg = ds::decode_game_msg(&bin.take()[..]).map_err(|e| e.to_string());
+				self.received(msg, ds::Encoding::MsgPack, ctx);
 			}
 			ws::Message::Close(_) => {
-				ctx.state().do_send(WebSocketMsg::Disconnected(ctx.address()));
+				ctx.state().server.do_send(WebSocketMsg::Disconnected(ctx.address()));
 
 				ctx.stop();
 			}
@@ -135,3 +206,15 @@ pub enum WebSocketMsg {
 pub struct ServerMsg {
 	pub msg: ds::ServerMsg
 }
+
+impl ServerMsg {
+	pub fn error(code: ds::ErrorCode, request: ds::GameMsg, message: String) -> ServerMsg {
+		ServerMsg {
+			msg: ds::ServerMsg::Error {
+				code: code,
+				request: Some(request),
+				message: message,
+			}
+		}
+	}
+}
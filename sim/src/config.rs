@@ -0,0 +1,88 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+// area of an OSM extract to use, in degrees
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct BoundingBox {
+	pub minlat: f64,
+	pub minlon: f64,
+	pub maxlat: f64,
+	pub maxlon: f64,
+}
+
+impl BoundingBox {
+	pub fn contains(&self, lat: f64, lon: f64) -> bool {
+		lat >= self.minlat && lat <= self.maxlat &&
+			lon >= self.minlon && lon <= self.maxlon
+	}
+}
+
+// parsed from "minlat,minlon,maxlat,maxlon"
+impl FromStr for BoundingBox {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<BoundingBox, String> {
+		let vals: Result<Vec<f64>, _> = s.split(',').map(|v| v.trim().parse()).collect();
+		match vals {
+			Ok(ref v) if v.len() == 4 => {
+				Ok(BoundingBox {
+					minlat: v[0],
+					minlon: v[1],
+					maxlat: v[2],
+					maxlon: v[3],
+				})
+			}
+			_ => Err(format!("bounding box must be minlat,minlon,maxlat,maxlon: {}", s))
+		}
+	}
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+	// length of the fixed game timestep, also the time between updates
+	pub update_interval_ms: u64,
+	// off-road walking speed (m/s)
+	pub walking_speed: f64,
+	pub road_speed: f64,
+	pub soldiers_per_side: i32,
+}
+
+impl Default for GameConfig {
+	fn default() -> GameConfig {
+		GameConfig {
+			update_interval_ms: 100,
+			walking_speed: 1.0,
+			road_speed: 2.0,
+			soldiers_per_side: 4,
+		}
+	}
+}
+
+impl GameConfig {
+	pub fn update_interval(&self) -> Duration {
+		Duration::from_millis(self.update_interval_ms)
+	}
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MapConfig {
+	// OSM file to load, the procedural flat map is used if not given
+	pub path: Option<String>,
+	pub bbox: Option<BoundingBox>,
+}
+
+impl MapConfig {
+	pub fn validate(&self) -> Result<(), String> {
+		if let Some(b) = self.bbox {
+			if self.path.is_none() {
+				return Err("a bounding box requires a map file".to_string());
+			}
+			if b.minlat >= b.maxlat || b.minlon >= b.maxlon {
+				return Err("empty bounding box".to_string());
+			}
+		}
+		Ok(())
+	}
+}
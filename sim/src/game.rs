@@ -0,0 +1,866 @@
+extern crate rand;
+extern crate geo;
+extern crate ds;
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::f64::consts::PI;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration};
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use crate::osm::MapData;
+use crate::nav::NavGraph;
+use crate::config::GameConfig;
+
+use ds::SoldierID;
+
+// how far a soldier can see (meters)
+const VIEW_RANGE: f64 = 300.0;
+// width of the vision cone (radians)
+const FIELD_OF_VIEW: f64 = 2.0 * PI / 3.0;
+// soldiers closer than this are noticed even outside the vision cone
+const AWARENESS_RANGE: f64 = 5.0;
+
+// every this many ticks all seen soldiers are reported, changed or not
+const KEYFRAME_INTERVAL: u64 = 50;
+
+// distance between soldiers when deployed (meters)
+const DEPLOYMENT_SPACING: f64 = 10.0;
+// soldiers deployed on unwalkable ground are moved to walkable ground
+// within this many steps of this length (meters)
+const DEPLOYMENT_SEARCH_STEPS: i32 = 50;
+const DEPLOYMENT_SEARCH_STEP: f64 = 2.0;
+
+const MAX_HEALTH: i32 = 100;
+// maximum distance a soldier can hit anything at (meters)
+const WEAPON_RANGE: f64 = 400.0;
+// time between shots (seconds)
+const FIRE_INTERVAL: f64 = 1.0;
+// chance to hit a target right next to the shooter
+const BASE_HIT_CHANCE: f64 = 0.9;
+// shooting while walking is less accurate
+const MOVING_HIT_FACTOR: f64 = 0.5;
+const HIT_DAMAGE: i32 = 40;
+
+// table describing, for each soldier, which soldiers he/she sees
+struct DetectionTable {
+	detected: Vec<Vec<ds::SoldierID>>,
+	curr_index: Vec<i32>,
+}
+
+impl DetectionTable {
+	fn new() -> DetectionTable {
+		DetectionTable {
+			detected: vec![[ds::SoldierID(-1); ds::MAX_NUM_SOLDIERS as usize].to_vec(); 4 as usize],
+			curr_index: vec![0; ds::MAX_NUM_SOLDIERS as usize],
+		}
+	}
+
+	fn add(&mut self, seen: ds::SoldierID, by: ds::SoldierID) {
+		let ds::SoldierID(s_index) = seen;
+		let cui = self.curr_index[s_index as usize];
+		if self.detected.len() <= cui as usize {
+			self.detected.push(vec![ds::SoldierID(-1); ds::MAX_NUM_SOLDIERS as usize].to_vec());
+		}
+		self.detected[cui as usize][s_index as usize] = by;
+		self.curr_index[s_index as usize] += 1;
+	}
+
+	// for each soldier, the set of soldiers he/she sees
+	fn seen_by(&self) -> Vec<HashSet<ds::SoldierID>> {
+		let mut ret = vec![HashSet::new(); ds::MAX_NUM_SOLDIERS as usize];
+		for i in 0..ds::MAX_NUM_SOLDIERS {
+			for j in 0..self.curr_index[i as usize] {
+				let SoldierID(by) = self.detected[j as usize][i as usize];
+				ret[by as usize].insert(SoldierID(i));
+			}
+		}
+		ret
+	}
+}
+
+// why a soldier ID from a client was rejected
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum SoldierError {
+	OutOfRange(ds::SoldierID),
+	// the slot exists but no soldier was deployed in it this game
+	NotInGame(ds::SoldierID),
+}
+
+impl fmt::Display for SoldierError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SoldierError::OutOfRange(SoldierID(i)) => write!(f, "soldier ID {} is out of range", i),
+			SoldierError::NotInGame(SoldierID(i)) => write!(f, "soldier {} is not in the game", i),
+		}
+	}
+}
+
+#[derive(PartialEq, Clone, Debug)]
+struct Soldier {
+	id: ds::SoldierID,
+	pos: ds::Position,
+	dir: ds::Direction,
+	// false for unused soldier slots
+	spawned: bool,
+	alive: bool,
+	health: i32,
+	side: ds::Side,
+	// waypoints to walk through, empty when not moving
+	path: VecDeque<ds::Position>,
+	target: Option<ds::SoldierID>,
+	// time until the next shot can be fired (seconds)
+	reload: f64,
+	view_range: f64,
+	fov: f64,
+}
+
+impl Soldier {
+	fn new() -> Soldier {
+		Soldier {
+			id: SoldierID(0),
+			pos: ds::Position { x: 0.0, y: 0.0 },
+			dir: ds::Direction(0.0),
+			spawned: false,
+			alive: false,
+			health: 0,
+			side: ds::Side::Blue,
+			path: VecDeque::new(),
+			target: None,
+			reload: 0.0,
+			view_range: VIEW_RANGE,
+			fov: FIELD_OF_VIEW,
+		}
+	}
+
+	// is the position within the given range and the vision cone
+	fn in_view(&self, pos: &ds::Position, range: f64) -> bool {
+		let dist = self.pos.dist(pos);
+		if dist > range {
+			return false;
+		}
+		if dist < AWARENESS_RANGE {
+			return true;
+		}
+		let ds::Direction(dir) = self.dir;
+		let to = self.pos.to_pos(pos);
+		let mut diff = to.y.atan2(to.x) - dir;
+		while diff > PI {
+			diff -= 2.0 * PI;
+		}
+		while diff < -PI {
+			diff += 2.0 * PI;
+		}
+		diff.abs() <= self.fov * 0.5
+	}
+
+	fn try_move(&mut self, time: Duration, speed: ds::Speed) -> bool {
+		if !self.alive {
+			return false;
+		}
+		match self.path.front() {
+			Some(pos) => {
+				let pos = *pos;
+				self.move_soldier(time, pos, speed);
+				true
+			}
+			None => {
+				false
+			}
+		}
+	}
+
+	fn move_soldier(&mut self, time: Duration, tgtpos: ds::Position, speed: ds::Speed) -> ds::SeenSoldierInfo {
+		if self.pos.dist(&tgtpos) < 1.0 {
+			self.path.pop_front();
+		} else {
+			let diff = self.pos.to_pos(&tgtpos).normalized();
+			self.pos.add(diff, speed, time);
+			self.dir = ds::Direction(diff.y.atan2(diff.x));
+		}
+		self.construct_sensor_info()
+	}
+
+	fn construct_sensor_info(&self) -> ds::SeenSoldierInfo {
+		ds::SeenSoldierInfo {
+			alive: self.alive,
+			position: self.pos,
+			direction: self.dir,
+			side: self.side
+		}
+	}
+
+	fn get_full_info(&self) -> ds::FullSoldierInfo {
+		ds::FullSoldierInfo {
+			internal: ds::InternalSoldierInfo {
+				health: self.health,
+			},
+			external: self.construct_sensor_info()
+		}
+	}
+}
+
+// identifies a client of the game, however it is connected
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug, Deserialize, Serialize)]
+pub struct ClientId(pub u64);
+
+pub struct GameState {
+	soldiers: Vec<Soldier>,
+	soldier_controllers: Vec<Option<ClientId>>,
+	map: Arc<MapData>,
+	nav: NavGraph,
+	// what was last reported to the controller of each soldier
+	last_reported: Vec<HashMap<ds::SoldierID, ds::SeenSoldierInfo>>,
+	ticks: u64,
+	client_sides: HashMap<ClientId, ds::Side>,
+	// clients that see everything but control nothing
+	spectators: BTreeSet<ClientId>,
+	// clients that have spectated this game. They know where everyone is
+	// so they can't join a side until the next game.
+	watched: HashSet<ClientId>,
+	map_info: ds::MapData,
+	// clients that have been sent the map of this game
+	map_sent: HashSet<ClientId>,
+	config: GameConfig,
+	// all randomness in the game comes from here so that games with the
+	// same seed and input play out identically
+	rng: XorShiftRng,
+	// simulated time per tick
+	timestep: Duration,
+	// messages to clients, waiting to be taken by the caller
+	outbox: Vec<(ClientId, ds::ServerMsg)>,
+}
+
+// roads are fast regardless of the terrain around them. Soldiers in
+// impassable terrain walk out of it at the normal speed.
+fn movement_speed(map: &MapData, config: &GameConfig, pos: &ds::Position) -> ds::Speed {
+	if map.on_road(pos) {
+		return ds::Speed { speed: config.road_speed };
+	}
+	let factor = map.speed_factor(pos);
+	ds::Speed { speed: config.walking_speed * if factor > 0.0 { factor } else { 1.0 } }
+}
+
+// the red side deploys south of the map center, the blue side north of it,
+// both facing the other side
+fn deployment_zone(side: ds::Side, bounds: &geo::Coordinate<f64>) -> (ds::Position, ds::Direction) {
+	let x = ds::deployment_line(side, &ds::Position::new(bounds.x, bounds.y));
+	match side {
+		ds::Side::Red  => (ds::Position::new(x, 0.0), ds::Direction(0.0)),
+		ds::Side::Blue => (ds::Position::new(x, 0.0), ds::Direction(PI)),
+	}
+}
+
+// the closest walkable position found in rings around the given one
+fn walkable_near(map: &MapData, pos: ds::Position) -> ds::Position {
+	if map.walkable(&pos) {
+		return pos;
+	}
+	for r in 1..DEPLOYMENT_SEARCH_STEPS + 1 {
+		let dist = r as f64 * DEPLOYMENT_SEARCH_STEP;
+		let n = 8 * r;
+		for k in 0..n {
+			let a = 2.0 * PI * k as f64 / n as f64;
+			let p = ds::Position::new(pos.x + dist * a.cos(), pos.y + dist * a.sin());
+			if map.walkable(&p) {
+				return p;
+			}
+		}
+	}
+	pos
+}
+
+// FNV-1a. Unlike DefaultHasher it is the same in every Rust release, so
+// checksums saved in replays stay valid.
+struct Checksum(u64);
+
+impl Checksum {
+	fn new() -> Checksum {
+		Checksum(0xcbf2_9ce4_8422_2325)
+	}
+
+	fn add(&mut self, v: u64) {
+		for i in 0..8 {
+			self.0 ^= (v >> (i * 8)) & 0xff;
+			self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+		}
+	}
+}
+
+// xorshift must not be seeded with all zeros
+fn seeded_rng(seed: u64) -> XorShiftRng {
+	XorShiftRng::from_seed([seed as u32, (seed >> 32) as u32, 0x9e37_79b9, 0x7f4a_7c15])
+}
+
+impl GameState {
+	pub fn new(map: Arc<MapData>, config: GameConfig, seed: u64) -> GameState {
+		let mut s = vec![Soldier::new(); ds::MAX_NUM_SOLDIERS as usize];
+		let mut controllers = vec![];
+		for i in 0..ds::MAX_NUM_SOLDIERS {
+			s[i as usize].id = SoldierID(i);
+			controllers.push(None);
+		}
+		let mut i = 0;
+		for side in [ds::Side::Red, ds::Side::Blue].iter() {
+			let (center, dir) = deployment_zone(*side, &map.bounds);
+			for j in 0..config.soldiers_per_side {
+				let offset = (j as f64 - (config.soldiers_per_side - 1) as f64 * 0.5) * DEPLOYMENT_SPACING;
+				s[i as usize].spawned = true;
+				s[i as usize].alive = true;
+				s[i as usize].health = MAX_HEALTH;
+				s[i as usize].side = *side;
+				s[i as usize].pos = walkable_near(&map, ds::Position::new(center.x, center.y + offset));
+				s[i as usize].dir = dir;
+				i += 1;
+			}
+		}
+		GameState {
+			soldiers: s,
+			soldier_controllers: controllers,
+			nav: NavGraph::new(&map.roads),
+			map_info: map.to_ds(),
+			map: map,
+			last_reported: vec![HashMap::new(); ds::MAX_NUM_SOLDIERS as usize],
+			ticks: 0,
+			client_sides: HashMap::new(),
+			spectators: BTreeSet::new(),
+			watched: HashSet::new(),
+			map_sent: HashSet::new(),
+			timestep: config.update_interval(),
+			config: config,
+			rng: seeded_rng(seed),
+			outbox: vec![],
+		}
+	}
+
+	// start a new game, keeping the connected clients and their sides.
+	// Spectators stop spectating and may choose a side or spectate again.
+	pub fn restart(&mut self, map: Arc<MapData>, config: GameConfig, seed: u64) {
+		let sides = std::mem::replace(&mut self.client_sides, HashMap::new());
+		let outbox = std::mem::replace(&mut self.outbox, vec![]);
+		*self = GameState::new(map, config, seed);
+		self.client_sides = sides;
+		self.outbox = outbox;
+	}
+
+	fn send(&mut self, client: ClientId, msg: ds::ServerMsg) {
+		self.outbox.push((client, msg));
+	}
+
+	fn send_error(&mut self, client: ClientId, code: ds::ErrorCode, request: ds::GameMsg, message: String) {
+		self.send(client, ds::ServerMsg::Error {
+			code: code,
+			request: Some(request),
+			message: message,
+		});
+	}
+
+	// all messages to clients since the last call, in the order they were sent
+	pub fn take_messages(&mut self) -> Vec<(ClientId, ds::ServerMsg)> {
+		std::mem::replace(&mut self.outbox, vec![])
+	}
+
+	pub fn send_map(&mut self, client: ClientId) {
+		self.map_sent.insert(client);
+		let map = self.map_info.clone();
+		self.send(client, ds::ServerMsg::MapData(map));
+	}
+
+	pub fn client_side(&self, client: ClientId) -> Option<ds::Side> {
+		self.client_sides.get(&client).cloned()
+	}
+
+	// clients and their sides, ordered by id
+	pub fn clients(&self) -> Vec<(ClientId, ds::Side)> {
+		let mut clients: Vec<(ClientId, ds::Side)> = self.client_sides.iter().map(|(c, s)| (*c, *s)).collect();
+		clients.sort_by_key(|&(c, _)| c);
+		clients
+	}
+
+	pub fn spectators(&self) -> Vec<ClientId> {
+		self.spectators.iter().cloned().collect()
+	}
+
+	// the client gives up its side, its soldiers are offered to its teammates
+	pub fn spectate(&mut self, client: ClientId) {
+		for i in 0..ds::MAX_NUM_SOLDIERS {
+			if self.soldier_controllers[i as usize] == Some(client) {
+				self.release_control(i as usize, client);
+			}
+		}
+		self.client_sides.remove(&client);
+		self.spectators.insert(client);
+		self.watched.insert(client);
+		if !self.map_sent.contains(&client) {
+			self.send_map(client);
+		}
+		let state = self.full_state();
+		self.send(client, ds::ServerMsg::FullState(state));
+	}
+
+	// all spawned soldiers as they are
+	pub fn full_state(&self) -> Vec<(ds::SoldierID, ds::FullSoldierInfo)> {
+		self.soldiers.iter()
+			.filter(|s| s.spawned)
+			.map(|s| (s.id, s.get_full_info()))
+			.collect()
+	}
+
+	// new clients are assigned to the side with fewer clients. Clients that
+	// spectated this game and come back are spectators again.
+	pub fn client_connected(&mut self, client: ClientId) {
+		if self.watched.contains(&client) {
+			self.spectate(client);
+			return;
+		}
+		let reds = self.client_sides.values().filter(|s| **s == ds::Side::Red).count();
+		let side = if reds * 2 < self.client_sides.len() + 1 {
+			ds::Side::Red
+		} else {
+			ds::Side::Blue
+		};
+		self.set_client_side(client, side);
+	}
+
+	// soldiers of the other side controlled by the client are released
+	pub fn set_client_side(&mut self, client: ClientId, side: ds::Side) {
+		for i in 0..ds::MAX_NUM_SOLDIERS {
+			if self.soldiers[i as usize].side != side &&
+				self.soldier_controllers[i as usize] == Some(client) {
+				self.soldier_controllers[i as usize] = None;
+			}
+		}
+		self.spectators.remove(&client);
+		self.client_sides.insert(client, side);
+		self.send(client, ds::ServerMsg::SideAssigned(side));
+	}
+
+	// all soldier IDs coming from clients must be looked up through here
+	pub fn soldier_index(&self, sid: ds::SoldierID) -> Result<usize, SoldierError> {
+		let SoldierID(i) = sid;
+		if i < 0 || i as usize >= self.soldiers.len() {
+			return Err(SoldierError::OutOfRange(sid));
+		}
+		if !self.soldiers[i as usize].spawned {
+			return Err(SoldierError::NotInGame(sid));
+		}
+		Ok(i as usize)
+	}
+
+	fn is_available(&self, sid: ds::SoldierID) -> bool {
+		let ds::SoldierID(i) = sid;
+		self.soldiers[i as usize].alive && self.soldier_controllers[i as usize].is_none()
+	}
+
+	// available soldiers of the client's side
+	pub fn available_soldiers(&self, client: ClientId) -> Vec<ds::SoldierID> {
+		let side = self.client_side(client);
+		self.soldiers.iter()
+			.filter(|p| Some(p.side) == side && self.is_available(p.id))
+			.map(|p| p.id)
+			.collect()
+	}
+
+	fn try_take_control(&mut self, sid: ds::SoldierID, from: ClientId) -> Result<(), (ds::ErrorCode, String)> {
+		let i = self.soldier_index(sid).map_err(|e| (ds::ErrorCode::InvalidSoldier, e.to_string()))?;
+		if !self.is_available(sid) {
+			return Err((ds::ErrorCode::SoldierUnavailable, format!("soldier {} is not available", i)));
+		}
+		if self.client_side(from) != Some(self.soldiers[i].side) {
+			return Err((ds::ErrorCode::WrongSide, format!("soldier {} is not on your side", i)));
+		}
+		self.give_control(i, from);
+		Ok(())
+	}
+
+	fn give_control(&mut self, i: usize, to: ClientId) {
+		self.soldier_controllers[i] = Some(to);
+		self.last_reported[i].clear();
+		let info = self.soldiers[i].get_full_info();
+		self.send(to, ds::ServerMsg::YouNowHaveControl(self.soldiers[i].id, info));
+		if !self.map_sent.contains(&to) {
+			self.send_map(to);
+		}
+	}
+
+	// the released soldier is offered to the rest of the side
+	fn release_control(&mut self, i: usize, client: ClientId) {
+		let sid = self.soldiers[i].id;
+		self.soldier_controllers[i] = None;
+		self.send(client, ds::ServerMsg::ControlLost(sid));
+		let side = self.soldiers[i].side;
+		for (c, s) in self.clients() {
+			if s == side && c != client {
+				let val = ds::ServerMsg::AvailableSoldiers(self.available_soldiers(c));
+				self.send(c, val);
+			}
+		}
+	}
+
+	fn transfer_control(&mut self, client: ClientId, sid: ds::SoldierID, to: ds::PlayerId)
+		-> Result<(), (ds::ErrorCode, String)> {
+		let i = self.controlled_soldier(client, sid)?;
+		let ds::PlayerId(p) = to;
+		match self.client_side(ClientId(p)) {
+			None => return Err((ds::ErrorCode::NoSuchPlayer, format!("no player {} in the game", p))),
+			Some(side) if side != self.soldiers[i].side => {
+				return Err((ds::ErrorCode::WrongSide, format!("player {} is on the other side", p)));
+			}
+			Some(_) => ()
+		}
+		if ClientId(p) != client {
+			self.send(client, ds::ServerMsg::ControlLost(sid));
+			self.give_control(i, ClientId(p));
+		}
+		Ok(())
+	}
+
+	// the index of a soldier the client controls
+	fn controlled_soldier(&self, client: ClientId, sid: ds::SoldierID) -> Result<usize, (ds::ErrorCode, String)> {
+		let i = self.soldier_index(sid).map_err(|e| (ds::ErrorCode::InvalidSoldier, e.to_string()))?;
+		if self.soldier_controllers[i] != Some(client) {
+			return Err((ds::ErrorCode::NotController, format!("you don't control soldier {}", i)));
+		}
+		Ok(i)
+	}
+
+	// there's no friendly fire, soldiers only engage the other side
+	fn engage_target(&self, i: usize, target: ds::SoldierID) -> Result<usize, (ds::ErrorCode, String)> {
+		let t = self.soldier_index(target).map_err(|e| (ds::ErrorCode::InvalidSoldier, e.to_string()))?;
+		if t == i {
+			return Err((ds::ErrorCode::InvalidSoldier, format!("soldier {} can't engage itself", i)));
+		}
+		if self.soldiers[t].side == self.soldiers[i].side {
+			return Err((ds::ErrorCode::WrongSide, format!("soldier {} is on your side", t)));
+		}
+		Ok(i)
+	}
+
+	// the waypoints for the soldier to walk to the position, around
+	// impassable terrain
+	fn route(&self, i: usize, to: ds::Position) -> Result<VecDeque<ds::Position>, (ds::ErrorCode, String)> {
+		if !to.x.is_finite() || !to.y.is_finite() {
+			return Err((ds::ErrorCode::InvalidPosition, format!("({}, {}) is not a position", to.x, to.y)));
+		}
+		if to.x.abs() > self.map.bounds.x * 0.5 || to.y.abs() > self.map.bounds.y * 0.5 {
+			return Err((ds::ErrorCode::InvalidPosition, format!("({:.1}, {:.1}) is outside the map", to.x, to.y)));
+		}
+		if !self.map.walkable(&to) {
+			return Err((ds::ErrorCode::InvalidPosition, format!("({:.1}, {:.1}) is not walkable", to.x, to.y)));
+		}
+		let map = &self.map;
+		self.nav.find_path(self.soldiers[i].pos, to, self.config.road_speed, self.config.walking_speed,
+				   |a, b| map.walkable_line(a, b))
+			.ok_or((ds::ErrorCode::InvalidPosition, format!("no route to ({:.1}, {:.1})", to.x, to.y)))
+	}
+
+	fn handle_take_control(&mut self, sid: ds::SoldierID, from: ClientId) {
+		match self.try_take_control(sid, from) {
+			Ok(()) => (),
+			Err((code, message)) => self.send_error(from, code, ds::GameMsg::TakeControl(sid), message)
+		}
+	}
+
+	// soldiers stop at the edge of impassable terrain, but one that is
+	// already on unwalkable ground may walk out of it
+	fn move_all(&mut self) {
+		for s in self.soldiers.iter_mut().filter(|s| !s.path.is_empty()) {
+			let speed = movement_speed(&self.map, &self.config, &s.pos);
+			let old = s.pos;
+			if s.try_move(self.timestep, speed) && !self.map.walkable(&s.pos) && self.map.walkable(&old) {
+				s.pos = old;
+				s.path.clear();
+			}
+		}
+	}
+
+	fn hit_chance(&self, shooter: &Soldier, target: &Soldier) -> f64 {
+		let dist = shooter.pos.dist(&target.pos);
+		if dist > WEAPON_RANGE {
+			return 0.0;
+		}
+		let falloff = 1.0 - dist / WEAPON_RANGE;
+		let chance = BASE_HIT_CHANCE * falloff * falloff;
+		if !shooter.path.is_empty() {
+			chance * MOVING_HIT_FACTOR
+		} else {
+			chance
+		}
+	}
+
+	// soldiers fire at their targets if they can see them
+	fn resolve_combat(&mut self) {
+		let dur = self.timestep;
+		let d = dur.as_secs() as f64 + dur.subsec_nanos() as f64 * 1e-9;
+		for i in 0..self.soldiers.len() {
+			let shooter = self.soldiers[i].clone();
+			if !shooter.alive {
+				continue;
+			}
+			self.soldiers[i].reload = (shooter.reload - d).max(0.0);
+			let tgt = match shooter.target.and_then(|SoldierID(t)| self.soldiers.get(t as usize)) {
+				Some(t) => t.clone(),
+				None => continue
+			};
+			if !tgt.alive {
+				self.soldiers[i].target = None;
+				continue;
+			}
+			if self.soldiers[i].reload > 0.0 || !self.can_see(&shooter, &tgt, &mut None) {
+				continue;
+			}
+			self.soldiers[i].reload = FIRE_INTERVAL;
+			if self.rng.gen::<f64>() < self.hit_chance(&shooter, &tgt) {
+				self.hit(tgt.id, HIT_DAMAGE);
+			}
+		}
+	}
+
+	fn hit(&mut self, sid: ds::SoldierID, damage: i32) {
+		let SoldierID(i) = sid;
+		let s = &mut self.soldiers[i as usize];
+		s.health = (s.health - damage).max(0);
+		if s.health == 0 {
+			s.alive = false;
+			s.path.clear();
+			s.target = None;
+		}
+		let info = s.get_full_info();
+		let alive = s.alive;
+		if let Some(c) = self.soldier_controllers[i as usize] {
+			self.send(c, ds::ServerMsg::StatusUpdate(sid, info.internal));
+			if !alive {
+				self.soldier_controllers[i as usize] = None;
+				self.send(c, ds::ServerMsg::ControlLost(sid));
+			}
+		}
+	}
+
+	fn find_detections(&self) -> DetectionTable {
+		let mut dt: DetectionTable = DetectionTable::new();
+		for s in self.soldiers.iter().filter(|s| s.spawned) {
+			let recps = self.detected_by(&s.id);
+			for recp in recps.into_iter() {
+				dt.add(s.id, recp);
+			}
+		}
+		dt
+	}
+
+	// only soldiers whose info changed since the last report are sent, except
+	// on keyframes. Soldiers no longer seen are reported in outsense.
+	fn construct_messages(&mut self, det_table: &DetectionTable) -> HashMap<ClientId, ds::ServerMsg> {
+		let mut msgs: HashMap<ClientId, ds::ServerMsg> = HashMap::new();
+		let keyframe = self.ticks % KEYFRAME_INTERVAL == 0;
+
+		for (i, now) in det_table.seen_by().into_iter().enumerate() {
+			let seer = SoldierID(i as i32);
+			let mut reported = HashMap::new();
+			for sid in now.iter() {
+				let SoldierID(j) = *sid;
+				let info = self.soldiers[j as usize].construct_sensor_info();
+				if let Some(c) = &self.soldier_controllers[i] {
+					if keyframe || self.last_reported[i].get(sid) != Some(&info) {
+						sensor_update(&mut msgs, *c, seer).add(*sid, info.clone());
+					}
+				}
+				reported.insert(*sid, info);
+			}
+			if let Some(c) = &self.soldier_controllers[i] {
+				let mut lost: Vec<SoldierID> = self.last_reported[i].keys()
+					.filter(|k| !now.contains(k))
+					.cloned()
+					.collect();
+				lost.sort_by_key(|&SoldierID(l)| l);
+				for l in lost {
+					sensor_update(&mut msgs, *c, seer).add_lost(l);
+				}
+			}
+			self.last_reported[i] = reported;
+		}
+		msgs
+	}
+
+	// advances the game by one fixed timestep
+	pub fn tick(&mut self) {
+		self.move_all();
+		self.resolve_combat();
+		let det_table = self.find_detections();
+		let mut msgs: Vec<(ClientId, ds::ServerMsg)> = self.construct_messages(&det_table).into_iter().collect();
+		msgs.sort_by_key(|&(c, _)| c);
+		self.outbox.extend(msgs);
+		if !self.spectators.is_empty() {
+			let state = self.full_state();
+			for c in self.spectators.iter() {
+				self.outbox.push((*c, ds::ServerMsg::FullState(state.clone())));
+			}
+		}
+		self.ticks += 1;
+	}
+
+	pub fn timestep(&self) -> Duration {
+		self.timestep
+	}
+
+	pub fn ticks(&self) -> u64 {
+		self.ticks
+	}
+
+	pub fn map_data(&self) -> &ds::MapData {
+		&self.map_info
+	}
+
+	// hash of the simulation state, equal for games that played out identically
+	pub fn checksum(&self) -> u64 {
+		let mut h = Checksum::new();
+		h.add(self.ticks);
+		for s in self.soldiers.iter() {
+			let SoldierID(id) = s.id;
+			let ds::Direction(dir) = s.dir;
+			h.add(id as u64);
+			h.add(s.pos.x.to_bits());
+			h.add(s.pos.y.to_bits());
+			h.add(dir.to_bits());
+			h.add(s.spawned as u64);
+			h.add(s.alive as u64);
+			h.add(s.health as u64);
+			h.add((s.side == ds::Side::Red) as u64);
+			h.add(s.reload.to_bits());
+			h.add(s.target.map_or(u64::max_value(), |SoldierID(t)| t as u64));
+			h.add(s.path.len() as u64);
+			for p in s.path.iter() {
+				h.add(p.x.to_bits());
+				h.add(p.y.to_bits());
+			}
+		}
+		h.add(self.rng.clone().next_u32() as u64);
+		h.0
+	}
+
+	// the dead can be seen but they don't see anything. The concealment of
+	// the seen soldier is looked up once it's needed and kept for the
+	// next seer, as it means going through all terrain.
+	fn can_see(&self, seer: &Soldier, seen: &Soldier, concealment: &mut Option<f64>) -> bool {
+		if !seer.alive || !seen.spawned {
+			return false;
+		}
+		if seer.id == seen.id {
+			return true;
+		}
+		if seer.pos.dist(&seen.pos) > seer.view_range {
+			return false;
+		}
+		let c = *concealment.get_or_insert_with(|| self.map.concealment(&seen.pos));
+		seer.in_view(&seen.pos, seer.view_range * c) && self.map.line_of_sight(&seer.pos, &seen.pos)
+	}
+
+	// all soldiers that can see the given soldier
+	fn detected_by(&self, sold: &ds::SoldierID) -> Vec<SoldierID> {
+		let ds::SoldierID(i) = *sold;
+		let seen = &self.soldiers[i as usize];
+		let mut concealment = None;
+		self.soldiers.iter()
+			.filter(|s| self.can_see(s, seen, &mut concealment))
+			.map(|s| s.id)
+			.collect()
+	}
+
+	pub fn client_disconnected(&mut self, client: ClientId) {
+		self.client_sides.remove(&client);
+		self.spectators.remove(&client);
+		self.map_sent.remove(&client);
+		for i in 0..ds::MAX_NUM_SOLDIERS {
+			match self.soldier_controllers[i as usize] {
+				Some(c) => {
+					if c == client {
+						self.soldier_controllers[i as usize] = None;
+					}
+				}
+				None => ()
+			}
+		}
+	}
+
+	pub fn game_msg(&mut self, client: ClientId, gmsg: ds::GameMsg) {
+		match gmsg.clone() {
+			ds::GameMsg::TakeControl(sid) => {
+				self.handle_take_control(sid, client);
+			}
+			ds::GameMsg::QueryStatus => {
+				let val = ds::ServerMsg::AvailableSoldiers(self.available_soldiers(client));
+				self.send(client, val);
+			}
+			ds::GameMsg::MoveTo(sid, pos) => {
+				let res = self.controlled_soldier(client, sid)
+					.and_then(|i| self.route(i, pos).map(|path| (i, path)));
+				match res {
+					Ok((i, path)) => self.soldiers[i].path = path,
+					Err((code, message)) => self.send_error(client, code, gmsg, message)
+				}
+			}
+			ds::GameMsg::Engage(sid, target) => {
+				let res = self.controlled_soldier(client, sid).and_then(|i| self.engage_target(i, target));
+				match res {
+					Ok(i) => self.soldiers[i].target = Some(target),
+					Err((code, message)) => self.send_error(client, code, gmsg, message)
+				}
+			}
+			ds::GameMsg::ChooseSide(side) => {
+				if self.watched.contains(&client) {
+					let message = "spectators can join a side in the next game".to_string();
+					self.send_error(client, ds::ErrorCode::NotAllowed, gmsg, message);
+				} else {
+					self.set_client_side(client, side);
+					let val = ds::ServerMsg::AvailableSoldiers(self.available_soldiers(client));
+					self.send(client, val);
+				}
+			}
+			ds::GameMsg::Spectate => {
+				self.spectate(client);
+			}
+			ds::GameMsg::ReleaseControl(sid) => {
+				match self.controlled_soldier(client, sid) {
+					Ok(i) => self.release_control(i, client),
+					Err((code, message)) => self.send_error(client, code, gmsg, message)
+				}
+			}
+			ds::GameMsg::TransferControl(sid, to) => {
+				match self.transfer_control(client, sid, to) {
+					Ok(()) => (),
+					Err((code, message)) => self.send_error(client, code, gmsg, message)
+				}
+			}
+			ds::GameMsg::ListPlayers => {
+				let ClientId(you) = client;
+				let players = self.clients().into_iter()
+					.map(|(ClientId(c), side)| (ds::PlayerId(c), side))
+					.collect();
+				self.send(client, ds::ServerMsg::PlayerList {
+					you: ds::PlayerId(you),
+					players: players,
+				});
+			}
+			// handled by the connection, the lobby and the room
+			ds::GameMsg::Hello { .. } |
+			ds::GameMsg::Init(_) | ds::GameMsg::Authenticate(_) |
+				ds::GameMsg::ListRooms | ds::GameMsg::CreateRoom(_) |
+				ds::GameMsg::JoinRoom(_) | ds::GameMsg::LeaveRoom => ()
+		}
+	}
+}
+
+fn sensor_update(map: &mut HashMap<ClientId, ds::ServerMsg>,
+		 recp: ClientId, seer: ds::SoldierID) -> &mut ds::SensorUpdate {
+	let msg = map.entry(recp).or_insert(ds::ServerMsg::SensorInfo(HashMap::new()));
+	match msg {
+		ds::ServerMsg::SensorInfo(upd) => {
+			upd.entry(seer).or_insert(ds::SensorUpdate::new())
+		}
+		_ => {
+			unreachable!()
+		}
+	}
+}
+
@@ -0,0 +1,92 @@
+extern crate geo;
+extern crate ds;
+
+use geo::*;
+
+pub fn to_coord(p: &ds::Position) -> Coordinate<f64> {
+	Coordinate { x: p.x, y: p.y }
+}
+
+fn orientation(a: Coordinate<f64>, b: Coordinate<f64>, c: Coordinate<f64>) -> f64 {
+	(b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+// true if segments a1-a2 and b1-b2 cross or touch
+pub fn segments_intersect(a1: Coordinate<f64>, a2: Coordinate<f64>,
+			  b1: Coordinate<f64>, b2: Coordinate<f64>) -> bool {
+	let d1 = orientation(b1, b2, a1);
+	let d2 = orientation(b1, b2, a2);
+	let d3 = orientation(a1, a2, b1);
+	let d4 = orientation(a1, a2, b2);
+	if ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0)) &&
+		((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0)) {
+		return true;
+	}
+	(d1 == 0.0 && on_segment(b1, b2, a1)) ||
+		(d2 == 0.0 && on_segment(b1, b2, a2)) ||
+		(d3 == 0.0 && on_segment(a1, a2, b1)) ||
+		(d4 == 0.0 && on_segment(a1, a2, b2))
+}
+
+fn on_segment(a: Coordinate<f64>, b: Coordinate<f64>, p: Coordinate<f64>) -> bool {
+	p.x >= a.x.min(b.x) && p.x <= a.x.max(b.x) &&
+		p.y >= a.y.min(b.y) && p.y <= a.y.max(b.y)
+}
+
+pub fn point_segment_dist(p: Coordinate<f64>, a: Coordinate<f64>, b: Coordinate<f64>) -> f64 {
+	let (dx, dy) = (b.x - a.x, b.y - a.y);
+	let len2 = dx * dx + dy * dy;
+	let t = if len2 == 0.0 {
+		0.0
+	} else {
+		(((p.x - a.x) * dx + (p.y - a.y) * dy) / len2).max(0.0).min(1.0)
+	};
+	let (cx, cy) = (a.x + t * dx, a.y + t * dy);
+	((p.x - cx) * (p.x - cx) + (p.y - cy) * (p.y - cy)).sqrt()
+}
+
+// even-odd rule, the ring is treated as closed
+pub fn ring_contains(ring: &LineString<f64>, p: Coordinate<f64>) -> bool {
+	let pts = &ring.0;
+	if pts.len() < 3 {
+		return false;
+	}
+	let mut inside = false;
+	let mut j = pts.len() - 1;
+	for i in 0..pts.len() {
+		let (pi, pj) = (pts[i], pts[j]);
+		if (pi.y > p.y) != (pj.y > p.y) &&
+			p.x < (pj.x - pi.x) * (p.y - pi.y) / (pj.y - pi.y) + pi.x {
+			inside = !inside;
+		}
+		j = i;
+	}
+	inside
+}
+
+pub fn segment_crosses_ring(ring: &LineString<f64>, a: Coordinate<f64>, b: Coordinate<f64>) -> bool {
+	let pts = &ring.0;
+	if pts.len() < 2 {
+		return false;
+	}
+	let mut j = pts.len() - 1;
+	for i in 0..pts.len() {
+		if segments_intersect(a, b, pts[j], pts[i]) {
+			return true;
+		}
+		j = i;
+	}
+	false
+}
+
+pub fn polygon_contains(poly: &Polygon<f64>, p: Coordinate<f64>) -> bool {
+	ring_contains(&poly.exterior, p) &&
+		!poly.interiors.iter().any(|r| ring_contains(r, p))
+}
+
+// true if any part of the segment a-b lies within the polygon
+pub fn segment_touches_polygon(poly: &Polygon<f64>, a: Coordinate<f64>, b: Coordinate<f64>) -> bool {
+	segment_crosses_ring(&poly.exterior, a, b) ||
+		poly.interiors.iter().any(|r| segment_crosses_ring(r, a, b)) ||
+		polygon_contains(poly, a)
+}
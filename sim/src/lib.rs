@@ -0,0 +1,13 @@
+// the game simulation, independent of how clients are connected
+#[macro_use]
+extern crate serde_derive;
+extern crate ds;
+
+pub mod config;
+mod geom;
+pub mod osm;
+mod nav;
+pub mod game;
+pub mod replay;
+
+pub use crate::game::{ClientId, GameState, SoldierError};
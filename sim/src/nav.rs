@@ -0,0 +1,159 @@
+extern crate geo;
+extern crate ds;
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+use geo::*;
+
+// road coordinates closer than this (meters) are merged into one node
+const NODE_MERGE_PRECISION: f64 = 0.01;
+
+// navigation graph built from the road network
+pub struct NavGraph {
+	nodes: Vec<Coordinate<f64>>,
+	edges: Vec<Vec<(usize, f64)>>,
+}
+
+#[derive(PartialEq)]
+struct Candidate {
+	estimate: f64,
+	cost: f64,
+	node: usize,
+	// the node the candidate was reached from
+	from: Option<usize>,
+	// whether it was reached along a road
+	road: bool,
+}
+
+impl Eq for Candidate {}
+
+// reversed so that BinaryHeap pops the lowest estimate first
+impl Ord for Candidate {
+	fn cmp(&self, other: &Candidate) -> Ordering {
+		other.estimate.partial_cmp(&self.estimate).unwrap_or(Ordering::Equal)
+	}
+}
+
+impl PartialOrd for Candidate {
+	fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+fn dist(a: Coordinate<f64>, b: Coordinate<f64>) -> f64 {
+	((a.x - b.x) * (a.x - b.x) + (a.y - b.y) * (a.y - b.y)).sqrt()
+}
+
+impl NavGraph {
+	pub fn new(roads: &[LineString<f64>]) -> NavGraph {
+		let mut graph = NavGraph {
+			nodes: vec![],
+			edges: vec![],
+		};
+		let mut index: HashMap<(i64, i64), usize> = HashMap::new();
+		for road in roads {
+			let mut prev: Option<usize> = None;
+			for c in road.0.iter() {
+				let key = ((c.x / NODE_MERGE_PRECISION).round() as i64,
+					   (c.y / NODE_MERGE_PRECISION).round() as i64);
+				let n = match index.get(&key) {
+					Some(n) => *n,
+					None => {
+						graph.nodes.push(*c);
+						graph.edges.push(vec![]);
+						index.insert(key, graph.nodes.len() - 1);
+						graph.nodes.len() - 1
+					}
+				};
+				if let Some(p) = prev {
+					if p != n {
+						let len = dist(graph.nodes[p], graph.nodes[n]);
+						graph.edges[p].push((n, len));
+						graph.edges[n].push((p, len));
+					}
+				}
+				prev = Some(n);
+			}
+		}
+		graph
+	}
+
+	// A* search for the fastest route from `from` to `to`. The route may
+	// leave and join the road network at any node, so walking straight to
+	// the target is also considered. Off-road legs are only taken if
+	// `passable` allows walking straight between their ends. Returns the
+	// waypoints, ending with `to`, or None if there's no route.
+	pub fn find_path<F>(&self, from: ds::Position, to: ds::Position,
+			    road_speed: f64, offroad_speed: f64, passable: F) -> Option<VecDeque<ds::Position>>
+		where F: Fn(&ds::Position, &ds::Position) -> bool {
+		let n = self.nodes.len();
+		let start = n;
+		let goal = n + 1;
+		let from_c = Coordinate { x: from.x, y: from.y };
+		let to_c = Coordinate { x: to.x, y: to.y };
+		let coord = |i: usize| -> Coordinate<f64> {
+			if i == start {
+				from_c
+			} else if i == goal {
+				to_c
+			} else {
+				self.nodes[i]
+			}
+		};
+		let pos = |i: usize| {
+			let c = coord(i);
+			ds::Position::new(c.x, c.y)
+		};
+		let heuristic = |i: usize| dist(coord(i), to_c) / road_speed;
+
+		// off-road legs are checked when the search gets to them rather than
+		// when they're found, as there's one from the start to every node
+		let mut closed = vec![false; n + 2];
+		let mut came_from = vec![None; n + 2];
+		let mut open = BinaryHeap::new();
+		open.push(Candidate { estimate: heuristic(start), cost: 0.0, node: start, from: None, road: true });
+
+		while let Some(Candidate { cost, node, from: prev, road, .. }) = open.pop() {
+			if closed[node] {
+				continue;
+			}
+			match prev {
+				Some(p) if !road && !passable(&pos(p), &pos(node)) => continue,
+				_ => ()
+			}
+			closed[node] = true;
+			came_from[node] = prev;
+			if node == goal {
+				break;
+			}
+			let here = coord(node);
+			let mut neighbours: Vec<(usize, f64, bool)> = vec![(goal, dist(here, to_c) / offroad_speed, false)];
+			if node == start {
+				neighbours.extend((0..n).map(|i| (i, dist(here, self.nodes[i]) / offroad_speed, false)));
+			} else {
+				neighbours.extend(self.edges[node].iter().map(|(i, len)| (*i, len / road_speed, true)));
+			}
+			for (next, step, road) in neighbours {
+				if !closed[next] {
+					let c = cost + step;
+					open.push(Candidate { estimate: c + heuristic(next), cost: c, node: next, from: Some(node), road: road });
+				}
+			}
+		}
+		if !closed[goal] {
+			return None;
+		}
+
+		let mut path = VecDeque::new();
+		let mut curr = goal;
+		while curr != start {
+			path.push_front(pos(curr));
+			curr = match came_from[curr] {
+				Some(p) => p,
+				None => break
+			};
+		}
+		Some(path)
+	}
+}
@@ -0,0 +1,583 @@
+extern crate osm_xml;
+extern crate geo;
+extern crate ds;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::sync::Arc;
+use geo::*;
+
+use crate::geom;
+use crate::config::{BoundingBox, MapConfig};
+
+// soldiers within this distance of the road center line are on the road
+const ROAD_HALF_WIDTH: f64 = 4.0;
+
+// size of the procedural map used when no OSM file is given (meters)
+const FLAT_MAP_SIZE: f64 = 1000.0;
+
+pub use ds::TerrainKind;
+
+// how the terrain affects the game
+pub trait TerrainEffects {
+	// multiplier for the off-road walking speed
+	fn speed_factor(&self) -> f64;
+	// multiplier for the distance at which soldiers in this terrain are seen
+	fn concealment(&self) -> f64;
+	fn passable(&self) -> bool;
+	fn blocks_sight(&self) -> bool;
+}
+
+impl TerrainEffects for TerrainKind {
+	fn speed_factor(&self) -> f64 {
+		match self {
+			TerrainKind::Forest   => 0.6,
+			TerrainKind::Water    => 0.0,
+			TerrainKind::Building => 0.0,
+			TerrainKind::Field    => 0.9,
+			TerrainKind::Urban    => 1.0,
+		}
+	}
+
+	fn concealment(&self) -> f64 {
+		match self {
+			TerrainKind::Forest   => 0.3,
+			TerrainKind::Water    => 1.0,
+			TerrainKind::Building => 0.2,
+			TerrainKind::Field    => 0.8,
+			TerrainKind::Urban    => 0.6,
+		}
+	}
+
+	fn passable(&self) -> bool {
+		match self {
+			TerrainKind::Water | TerrainKind::Building => false,
+			_ => true
+		}
+	}
+
+	fn blocks_sight(&self) -> bool {
+		match self {
+			TerrainKind::Forest | TerrainKind::Building => true,
+			_ => false
+		}
+	}
+}
+
+pub struct Terrain {
+	pub kind: TerrainKind,
+	pub area: MultiPolygon<f64>,
+	// bounding box, min and max corners
+	bbox: (Coordinate<f64>, Coordinate<f64>),
+}
+
+impl Terrain {
+	pub fn new(kind: TerrainKind, area: MultiPolygon<f64>) -> Terrain {
+		let bbox = area.0.iter()
+			.flat_map(|p| p.exterior.0.iter())
+			.fold((Coordinate { x: std::f64::INFINITY, y: std::f64::INFINITY },
+			       Coordinate { x: -std::f64::INFINITY, y: -std::f64::INFINITY }),
+			|(min, max), c| (Coordinate { x: min.x.min(c.x), y: min.y.min(c.y) },
+					 Coordinate { x: max.x.max(c.x), y: max.y.max(c.y) }));
+		Terrain {
+			kind: kind,
+			area: area,
+			bbox: bbox,
+		}
+	}
+
+	pub fn contains(&self, p: Coordinate<f64>) -> bool {
+		p.x >= self.bbox.0.x && p.x <= self.bbox.1.x &&
+			p.y >= self.bbox.0.y && p.y <= self.bbox.1.y &&
+			self.area.0.iter().any(|poly| geom::polygon_contains(poly, p))
+	}
+
+	// polygons block a line unless an end point that may be inside is
+	// inside the polygon, so that e.g. a soldier inside a building can
+	// still look out
+	fn blocks_line(&self, a: Coordinate<f64>, b: Coordinate<f64>, a_inside: bool, b_inside: bool) -> bool {
+		if a.x.max(b.x) < self.bbox.0.x || a.x.min(b.x) > self.bbox.1.x ||
+			a.y.max(b.y) < self.bbox.0.y || a.y.min(b.y) > self.bbox.1.y {
+			return false;
+		}
+		self.area.0.iter()
+			.filter(|poly| !(a_inside && geom::polygon_contains(poly, a)) &&
+				!(b_inside && geom::polygon_contains(poly, b)))
+			.any(|poly| geom::segment_touches_polygon(poly, a, b))
+	}
+}
+
+pub struct MapData {
+	pub roads: Vec<LineString<f64>>,
+	pub terrain: Vec<Terrain>,
+	pub bounds: Coordinate<f64>
+}
+
+fn to_position(c: &Coordinate<f64>) -> ds::Position {
+	ds::Position::new(c.x, c.y)
+}
+
+fn to_positions(ls: &LineString<f64>) -> Vec<ds::Position> {
+	ls.0.iter().map(to_position).collect()
+}
+
+impl MapData {
+	// the map in the form sent to the clients
+	pub fn to_ds(&self) -> ds::MapData {
+		ds::MapData {
+			roads: self.roads.iter().map(to_positions).collect(),
+			terrain: self.terrain.iter()
+				.map(|t| ds::TerrainArea {
+					kind: t.kind,
+					polygons: t.area.0.iter()
+						.map(|p| ds::Polygon {
+							exterior: to_positions(&p.exterior),
+							interiors: p.interiors.iter().map(to_positions).collect(),
+						})
+						.collect(),
+				})
+				.collect(),
+			bounds: to_position(&self.bounds),
+		}
+	}
+
+	fn terrain_at<'a>(&'a self, pos: &ds::Position) -> impl Iterator<Item = &'a Terrain> {
+		let p = geom::to_coord(pos);
+		self.terrain.iter().filter(move |t| t.contains(p))
+	}
+
+	pub fn speed_factor(&self, pos: &ds::Position) -> f64 {
+		self.terrain_at(pos).fold(1.0, |acc, t| acc.min(t.kind.speed_factor()))
+	}
+
+	pub fn concealment(&self, pos: &ds::Position) -> f64 {
+		self.terrain_at(pos).fold(1.0, |acc, t| acc.min(t.kind.concealment()))
+	}
+
+	// roads lead over water, e.g. on bridges
+	pub fn walkable(&self, pos: &ds::Position) -> bool {
+		self.on_road(pos) || self.terrain_at(pos).all(|t| t.kind.passable())
+	}
+
+	pub fn on_road(&self, pos: &ds::Position) -> bool {
+		let p = geom::to_coord(pos);
+		self.roads.iter()
+			.any(|r| r.0.windows(2)
+			     .any(|w| geom::point_segment_dist(p, w[0], w[1]) < ROAD_HALF_WIDTH))
+	}
+
+	pub fn line_of_sight(&self, from: &ds::Position, to: &ds::Position) -> bool {
+		let a = geom::to_coord(from);
+		let b = geom::to_coord(to);
+		!self.terrain.iter()
+			.filter(|t| t.kind.blocks_sight())
+			.any(|t| t.blocks_line(a, b, true, true))
+	}
+
+	// whether one can walk straight from one position to the other, e.g.
+	// out of a building or along a bridge. Only an end point that isn't
+	// walkable may be inside impassable terrain.
+	pub fn walkable_line(&self, from: &ds::Position, to: &ds::Position) -> bool {
+		let a = geom::to_coord(from);
+		let b = geom::to_coord(to);
+		let (a_inside, b_inside) = (!self.walkable(from), !self.walkable(to));
+		self.terrain.iter()
+			.filter(|t| !t.kind.passable())
+			.filter(|t| t.blocks_line(a, b, a_inside, b_inside))
+			.all(|t| self.road_through(t, from, to))
+	}
+
+	// whether the line is on a road wherever it is in the terrain, checked
+	// at steps of the road half width. A line only grazing the terrain
+	// between the steps isn't.
+	fn road_through(&self, t: &Terrain, from: &ds::Position, to: &ds::Position) -> bool {
+		let steps = (from.dist(to) / ROAD_HALF_WIDTH).ceil() as usize;
+		let mut inside = (0..steps + 1)
+			.map(|i| {
+				let f = if steps == 0 { 0.0 } else { i as f64 / steps as f64 };
+				ds::Position::new(from.x + (to.x - from.x) * f, from.y + (to.y - from.y) * f)
+			})
+			.filter(|p| t.contains(geom::to_coord(p)))
+			.peekable();
+		inside.peek().is_some() && inside.all(|p| self.on_road(&p))
+	}
+}
+
+pub fn load_map(config: &MapConfig) -> Result<MapData, String> {
+	match config.path {
+		Some(ref path) => load_osm(path, config.bbox.as_ref()),
+		None => {
+			eprintln!("No map file given, using a flat map");
+			Ok(flat_map())
+		}
+	}
+}
+
+// the default map and the named maps a game can be started on
+pub struct Maps {
+	default: Arc<MapData>,
+	named: HashMap<String, Arc<MapData>>,
+}
+
+impl Maps {
+	// the default map if no name is given
+	pub fn get(&self, name: Option<&str>) -> Option<Arc<MapData>> {
+		match name {
+			Some(n) => self.named.get(n).cloned(),
+			None => Some(self.default.clone())
+		}
+	}
+}
+
+pub fn load_maps(default: &MapConfig, named: &HashMap<String, MapConfig>) -> Result<Maps, String> {
+	let mut maps = Maps {
+		default: Arc::new(load_map(default)?),
+		named: HashMap::new(),
+	};
+	for (name, config) in named.iter() {
+		eprintln!("Loading map {}", name);
+		let map = load_map(config).map_err(|e| format!("map {}: {}", name, e))?;
+		maps.named.insert(name.clone(), Arc::new(map));
+	}
+	Ok(maps)
+}
+
+// open terrain with two roads crossing at the center
+pub fn flat_map() -> MapData {
+	let half = FLAT_MAP_SIZE * 0.5;
+	let center = Coordinate { x: 0.0, y: 0.0 };
+	MapData {
+		roads: [(half, 0.0), (-half, 0.0), (0.0, half), (0.0, -half)].iter()
+			.map(|&(x, y)| vec![center, Coordinate { x: x, y: y }].into_iter().collect())
+			.collect(),
+		terrain: vec![],
+		bounds: Coordinate { x: FLAT_MAP_SIZE, y: FLAT_MAP_SIZE },
+	}
+}
+
+pub fn load_osm(path: &str, bbox: Option<&BoundingBox>) -> Result<MapData, String> {
+	let f = File::open(path).map_err(|e| format!("could not open {}: {}", path, e))?;
+	let doc = osm_xml::OSM::parse(f).map_err(|e| format!("could not parse {}: {:?}", path, e))?;
+	let rel_info = relation_reference_statistics(&doc);
+	let way_info = way_reference_statistics(&doc);
+	let poly_count = doc.ways.values().fold(0, |acc, way| {
+		if way.is_polygon() {
+			return acc + 1
+		}
+
+		acc
+	});
+
+	eprintln!("Node count {}", doc.nodes.len());
+	eprintln!("Way count {}", doc.ways.len());
+	eprintln!("Polygon count {}", poly_count);
+	eprintln!("Relation count {}", doc.relations.len());
+	eprintln!("Tag count {}", tag_count(&doc));
+
+	eprintln!("Way reference count: {}, invalid references: {}",  way_info.0, way_info.1);
+	eprintln!("Relation reference count: {}, resolved: {}, unresolved: {}", rel_info.0, rel_info.1, rel_info.2);
+
+	let map = gen_map_data(&doc, bbox);
+	eprintln!("have map: {}\n", map.roads.len());
+	eprintln!("terrain areas: {}\n", map.terrain.len());
+	eprintln!("bounds: {:?}\n", map.bounds);
+	Ok(map)
+}
+
+fn way_is_highway(way: &osm_xml::Way) -> bool {
+	return way.tags.to_owned().into_iter().any(move |t| t.key == "highway");
+}
+
+fn tag_value<'a>(tags: &'a [osm_xml::Tag], key: &str) -> Option<&'a str> {
+	tags.iter().find(|t| t.key == key).map(|t| t.val.as_str())
+}
+
+fn terrain_kind(tags: &[osm_xml::Tag]) -> Option<TerrainKind> {
+	let natural = tag_value(tags, "natural");
+	let landuse = tag_value(tags, "landuse");
+	if tag_value(tags, "building").is_some() {
+		return Some(TerrainKind::Building);
+	}
+	if natural == Some("water") || tag_value(tags, "waterway") == Some("riverbank") {
+		return Some(TerrainKind::Water);
+	}
+	if natural == Some("wood") {
+		return Some(TerrainKind::Forest);
+	}
+	match landuse {
+		Some("forest") => Some(TerrainKind::Forest),
+		Some("reservoir") | Some("basin") => Some(TerrainKind::Water),
+		Some("residential") | Some("commercial") | Some("industrial") | Some("retail") => Some(TerrainKind::Urban),
+		Some("farmland") | Some("farmyard") | Some("meadow") | Some("grass") | Some("orchard") | Some("vineyard") => Some(TerrainKind::Field),
+		_ => {
+			match natural {
+				Some("grassland") | Some("heath") | Some("scrub") => Some(TerrainKind::Field),
+				_ => None
+			}
+		}
+	}
+}
+
+fn is_closed(ls: &LineString<f64>) -> bool {
+	ls.0.len() >= 4 && ls.0.first() == ls.0.last()
+}
+
+// closed ways tagged e.g. landuse=forest or building=yes
+fn way_terrain(doc: &osm_xml::OSM, center: Coordinate<f64>, way: &osm_xml::Way,
+	       bbox: Option<&BoundingBox>) -> Option<Terrain> {
+	let kind = terrain_kind(&way.tags)?;
+	if !way_in_bbox(doc, way, bbox) {
+		return None;
+	}
+	let ls = resolve_references(doc, center, way.to_owned())?;
+	if !is_closed(&ls) {
+		return None;
+	}
+	Some(Terrain::new(kind, MultiPolygon(vec![Polygon::new(ls, vec![])])))
+}
+
+// join the member ways of a multipolygon relation into closed rings
+fn assemble_rings(mut parts: Vec<Vec<Coordinate<f64>>>) -> Vec<LineString<f64>> {
+	let mut rings = vec![];
+	while let Some(mut ring) = parts.pop() {
+		while ring.len() > 1 && ring.first() != ring.last() {
+			let end = ring[ring.len() - 1];
+			let next = parts.iter().position(|p| p.first() == Some(&end) || p.last() == Some(&end));
+			match next {
+				Some(i) => {
+					let mut p = parts.swap_remove(i);
+					if p.first() != Some(&end) {
+						p.reverse();
+					}
+					ring.extend(p.into_iter().skip(1));
+				}
+				None => break
+			}
+		}
+		let ls: LineString<f64> = ring.into_iter().collect();
+		if is_closed(&ls) {
+			rings.push(ls);
+		}
+	}
+	rings
+}
+
+fn relation_terrain(doc: &osm_xml::OSM, center: Coordinate<f64>, rel: &osm_xml::Relation,
+		    bbox: Option<&BoundingBox>) -> Option<Terrain> {
+	if tag_value(&rel.tags, "type") != Some("multipolygon") {
+		return None;
+	}
+	let kind = terrain_kind(&rel.tags)?;
+	let mut outers = vec![];
+	let mut inners = vec![];
+	let mut in_bbox = false;
+	for member in rel.members.iter() {
+		if let osm_xml::Member::Way(ref r, ref role) = *member {
+			if let osm_xml::Reference::Way(w) = doc.resolve_reference(r) {
+				in_bbox = in_bbox || way_in_bbox(doc, w, bbox);
+				if let Some(ls) = resolve_references(doc, center, w.to_owned()) {
+					if role == "inner" {
+						inners.push(ls.0);
+					} else {
+						outers.push(ls.0);
+					}
+				}
+			}
+		}
+	}
+	if !in_bbox {
+		return None;
+	}
+	let mut inners = assemble_rings(inners);
+	let polys: Vec<Polygon<f64>> = assemble_rings(outers).into_iter()
+		.map(|outer| {
+			let (mine, rest): (Vec<_>, Vec<_>) = inners.drain(..)
+				.partition(|inner| geom::ring_contains(&outer, inner.0[0]));
+			inners = rest;
+			Polygon::new(outer, mine)
+		})
+		.collect();
+	if polys.is_empty() {
+		None
+	} else {
+		Some(Terrain::new(kind, MultiPolygon(polys)))
+	}
+}
+
+fn resolve_node(doc: &osm_xml::OSM, node: osm_xml::UnresolvedReference) -> Option<osm_xml::Node> {
+	match doc.resolve_reference(&node) {
+		osm_xml::Reference::Node(n) => Some(n.to_owned()),
+		_                           => None
+	}
+}
+
+fn node_to_coordinate(center: Coordinate<f64>, node: osm_xml::Node) -> Coordinate<f64> {
+	let c = normalise_latlong(Coordinate { x: node.lat, y: node.lon});
+	Coordinate {
+		x: c.x - center.x,
+		y: c.y - center.y
+	}
+}
+
+fn resolve_references(doc: &osm_xml::OSM, center: Coordinate<f64>, way: osm_xml::Way) -> Option<LineString<f64>> {
+	let items: Option<Vec<_>> = way.nodes.into_iter()
+		.map(|n| resolve_node(doc, n))
+		.collect();
+	items.and_then(|i| Some(i.into_iter().map(|n| node_to_coordinate(center, n)).collect()))
+}
+
+fn node_in_bbox(node: &osm_xml::Node, bbox: Option<&BoundingBox>) -> bool {
+	match bbox {
+		Some(b) => b.contains(node.lat, node.lon),
+		None    => true
+	}
+}
+
+fn way_in_bbox(doc: &osm_xml::OSM, way: &osm_xml::Way, bbox: Option<&BoundingBox>) -> bool {
+	way.nodes.iter()
+		.filter_map(|n| resolve_node(doc, n.to_owned()))
+		.any(|n| node_in_bbox(&n, bbox))
+}
+
+// the parts of the way that are within the bounding box
+fn clip_way(doc: &osm_xml::OSM, center: Coordinate<f64>, way: &osm_xml::Way,
+	    bbox: Option<&BoundingBox>) -> Vec<LineString<f64>> {
+	let nodes: Option<Vec<_>> = way.nodes.iter()
+		.map(|n| resolve_node(doc, n.to_owned()))
+		.collect();
+	let mut ret = vec![];
+	let mut curr: Vec<Coordinate<f64>> = vec![];
+	for n in nodes.unwrap_or(vec![]) {
+		if node_in_bbox(&n, bbox) {
+			curr.push(node_to_coordinate(center, n));
+		} else {
+			if curr.len() >= 2 {
+				ret.push(curr.drain(..).collect());
+			}
+			curr.clear();
+		}
+	}
+	if curr.len() >= 2 {
+		ret.push(curr.into_iter().collect());
+	}
+	ret
+}
+
+fn normalise_latlong(c: Coordinate<f64>) -> Coordinate<f64> {
+	Coordinate {
+		x: c.x * 111320.,
+		y: c.y * 111320. * c.x.to_radians().cos()
+	}
+}
+
+fn find_bounds(doc: &osm_xml::OSM) -> osm_xml::Bounds {
+	let val = doc.nodes.values()
+		.fold((180.0_f64, 180.0_f64, -180.0_f64, -180.0_f64),
+		|(minlat, minlon, maxlat, maxlon), n|
+		(minlat.min(n.lat),
+		minlon.min(n.lon),
+		maxlat.max(n.lat),
+		maxlon.max(n.lon)));
+	osm_xml::Bounds {
+		minlat: val.0,
+		minlon: val.1,
+		maxlat: val.2,
+		maxlon: val.3,
+	}
+}
+
+// bounds in meters
+fn normalise_bounds(bounds: osm_xml::Bounds) -> osm_xml::Bounds {
+	let v1 = normalise_latlong(Coordinate { x: bounds.minlat, y: bounds.minlon });
+	let v2 = normalise_latlong(Coordinate { x: bounds.maxlat, y: bounds.maxlon });
+	osm_xml::Bounds {
+		minlat: v1.x,
+		minlon: v1.y,
+		maxlat: v2.x,
+		maxlon: v2.y,
+	}
+}
+
+fn find_center(bounds: osm_xml::Bounds) -> Coordinate<f64> {
+	Coordinate {
+		x: (bounds.minlat + bounds.maxlat) / 2.,
+		y: (bounds.minlon + bounds.maxlon) / 2.
+	}
+}
+
+fn gen_map_data(doc: &osm_xml::OSM, bbox: Option<&BoundingBox>) -> MapData {
+	let bounds = match (bbox, doc.bounds) {
+		(Some(b), _)    => osm_xml::Bounds {
+			minlat: b.minlat,
+			minlon: b.minlon,
+			maxlat: b.maxlat,
+			maxlon: b.maxlon,
+		},
+		(None, Some(b)) => b,
+		(None, None)    => find_bounds(doc)
+	};
+	let bounds = normalise_bounds(bounds);
+
+	let center = find_center(bounds);
+	MapData {
+		roads: doc.ways.values()
+			.filter(|way| (way_is_highway(way)))
+			.flat_map(|way| clip_way(doc, center, way, bbox))
+			.collect(),
+		terrain: doc.ways.values()
+			.filter_map(|way| way_terrain(doc, center, way, bbox))
+			.chain(doc.relations.values()
+			       .filter_map(|rel| relation_terrain(doc, center, rel, bbox)))
+			.collect(),
+		bounds: Coordinate {
+			x: bounds.maxlat - bounds.minlat,
+			y: bounds.maxlon - bounds.minlon
+		}
+	}
+}
+
+fn relation_reference_statistics(doc: &osm_xml::OSM) -> (usize, usize, usize) {
+	doc.relations.values()
+		.flat_map(|relation| relation.members.iter())
+		.fold((0, 0, 0), |acc, member| {
+			let el_ref = match *member {
+				osm_xml::Member::Node(ref el_ref, _) => el_ref,
+				osm_xml::Member::Way(ref el_ref, _) => el_ref,
+				osm_xml::Member::Relation(ref el_ref, _) => el_ref,
+			};
+
+			match doc.resolve_reference(&el_ref) {
+				osm_xml::Reference::Unresolved => (acc.0 + 1, acc.1, acc.2 + 1),
+				osm_xml::Reference::Node(_)     |
+					osm_xml::Reference::Way(_)      |
+					osm_xml::Reference::Relation(_) => (acc.0 + 1, acc.1 + 1, acc.2)
+			}
+		})
+}
+
+fn way_reference_statistics(doc: &osm_xml::OSM) -> (usize, usize) {
+	doc.ways.values()
+		.flat_map(|way| way.nodes.iter())
+		.fold((0, 0), |acc, node| {
+			match doc.resolve_reference(&node) {
+				osm_xml::Reference::Node(_) => (acc.0 + 1, acc.1),
+				osm_xml::Reference::Unresolved  |
+					osm_xml::Reference::Way(_)      |
+					osm_xml::Reference::Relation(_) => (acc.0, acc.1 + 1)
+			}
+		})
+}
+
+fn tag_count(doc: &osm_xml::OSM) -> usize {
+	let node_tag_count = doc.nodes.values()
+		.map(|node| node.tags.len())
+		.fold(0, |acc, c| acc + c);
+	let way_tag_count = doc.ways.values()
+		.map(|way| way.tags.len())
+		.fold(0, |acc, c| acc + c);
+	let relation_tag_count = doc.relations.values()
+		.map(|relation| relation.tags.len())
+		.fold(0, |acc, c| acc + c);
+
+	node_tag_count + way_tag_count + relation_tag_count
+} 
@@ -0,0 +1,204 @@
+extern crate rmp_serde;
+extern crate serde;
+extern crate ds;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use rmp_serde::{Deserializer, Serializer};
+
+use crate::game::*;
+use crate::osm::Maps;
+use crate::config::GameConfig;
+
+// the state of the game is written to the replay every this many ticks
+const CHECKPOINT_INTERVAL: u64 = 100;
+
+// something a client did that affects the game
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum ClientEvent {
+	Connected,
+	Disconnected,
+	Msg(ds::GameMsg),
+}
+
+impl ClientEvent {
+	pub fn apply(self, client: ClientId, game: &mut GameState) {
+		match self {
+			ClientEvent::Connected => game.client_connected(client),
+			ClientEvent::Disconnected => game.client_disconnected(client),
+			ClientEvent::Msg(gmsg) => game.game_msg(client, gmsg),
+		}
+	}
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReplayEvent {
+	// number of ticks run before the event
+	pub tick: u64,
+	pub client: ClientId,
+	pub event: ClientEvent,
+}
+
+// the start of a replay file, after the protocol version and before the
+// records
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReplayHeader {
+	pub config: GameConfig,
+	pub scenario: ds::Scenario,
+	// clients already in the game when it started
+	pub clients: Vec<(ClientId, ds::Side)>,
+	pub spectators: Vec<ClientId>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum ReplayRecord {
+	Event(ReplayEvent),
+	// the state of the game after this many ticks
+	Checkpoint { ticks: u64, checksum: u64 },
+}
+
+// writes the replay of a game while it is played, every record as it
+// happens, so that games that never end are saved too and nothing is lost
+// if the server stops
+pub struct Recorder {
+	file: File,
+	path: String,
+}
+
+impl Recorder {
+	pub fn create(dir: &str, room: &str, header: &ReplayHeader) -> Result<Recorder, String> {
+		let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or(0);
+		let room: String = room.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect();
+		// a game restarted with the same seed within the same second gets a
+		// numbered file rather than overwriting the previous replay
+		let mut n = 0;
+		let (file, path) = loop {
+			let name = match n {
+				0 => format!("{}-{}-{}.replay", room, time, header.scenario.seed),
+				_ => format!("{}-{}-{}-{}.replay", room, time, header.scenario.seed, n)
+			};
+			let path = Path::new(dir).join(name);
+			match OpenOptions::new().write(true).create_new(true).open(&path) {
+				Ok(f) => break (f, path),
+				Err(ref e) if e.kind() == ErrorKind::AlreadyExists => n += 1,
+				Err(e) => return Err(format!("could not create {}: {}", path.display(), e))
+			}
+		};
+		let mut rec = Recorder {
+			file: file,
+			path: path.display().to_string(),
+		};
+		// the GameMsgs in the events are only understood by the same version
+		rec.write(&ds::PROTOCOL_VERSION)?;
+		rec.write(header)?;
+		Ok(rec)
+	}
+
+	fn write<T: Serialize>(&mut self, val: &T) -> Result<(), String> {
+		let mut buf = Vec::new();
+		val.serialize(&mut Serializer::new(&mut buf)).map_err(|e| format!("{:?}", e))?;
+		self.file.write_all(&buf).map_err(|e| format!("could not write {}: {}", self.path, e))
+	}
+
+	pub fn record(&mut self, tick: u64, client: ClientId, event: ClientEvent) -> Result<(), String> {
+		self.write(&ReplayRecord::Event(ReplayEvent {
+			tick: tick,
+			client: client,
+			event: event,
+		}))
+	}
+
+	// called after every tick, the state of the game is written every now
+	// and then so that playing the replay back can be checked against it
+	pub fn ticked(&mut self, game: &GameState) -> Result<(), String> {
+		if game.ticks() % CHECKPOINT_INTERVAL == 0 {
+			self.checkpoint(game)
+		} else {
+			Ok(())
+		}
+	}
+
+	fn checkpoint(&mut self, game: &GameState) -> Result<(), String> {
+		self.write(&ReplayRecord::Checkpoint {
+			ticks: game.ticks(),
+			checksum: game.checksum(),
+		})
+	}
+
+	// stores the final state of the game. Returns where the replay was
+	// saved, or None if the game never started and the file was removed.
+	pub fn finish(mut self, game: &GameState) -> Result<Option<String>, String> {
+		if game.ticks() == 0 {
+			fs::remove_file(&self.path).map_err(|e| format!("could not remove {}: {}", self.path, e))?;
+			return Ok(None);
+		}
+		self.checkpoint(game)?;
+		Ok(Some(self.path))
+	}
+}
+
+// a recorded game, read back from a file
+#[derive(Debug)]
+pub struct Replay {
+	pub header: ReplayHeader,
+	pub events: Vec<ReplayEvent>,
+	// ticks run and the checksum of the game state then
+	pub checkpoints: Vec<(u64, u64)>,
+}
+
+impl Replay {
+	// a replay that was cut short, e.g. by a crash, is read up to its last
+	// complete record
+	pub fn load(path: &str) -> Result<Replay, String> {
+		let buf = fs::read(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+		let mut de = Deserializer::from_slice(&buf[..]);
+		let version: u32 = Deserialize::deserialize(&mut de)
+			.map_err(|e| format!("could not parse {}: {:?}", path, e))?;
+		if version != ds::PROTOCOL_VERSION {
+			return Err(format!("{} was recorded with protocol version {}, this is version {}",
+					   path, version, ds::PROTOCOL_VERSION));
+		}
+		let header = Deserialize::deserialize(&mut de).map_err(|e| format!("could not parse {}: {:?}", path, e))?;
+		let mut replay = Replay {
+			header: header,
+			events: vec![],
+			checkpoints: vec![],
+		};
+		while !de.get_ref().is_empty() {
+			match Deserialize::deserialize(&mut de) {
+				Ok(ReplayRecord::Event(ev)) => replay.events.push(ev),
+				Ok(ReplayRecord::Checkpoint { ticks, checksum }) => replay.checkpoints.push((ticks, checksum)),
+				Err(_) => break
+			}
+		}
+		Ok(replay)
+	}
+
+	// length of the recorded game
+	pub fn ticks(&self) -> u64 {
+		let events = self.events.last().map_or(0, |e| e.tick);
+		let checkpoints = self.checkpoints.last().map_or(0, |&(t, _)| t);
+		events.max(checkpoints)
+	}
+
+	// the game as it was when the recording started
+	pub fn start(&self, maps: &Maps) -> Result<GameState, String> {
+		let h = &self.header;
+		let map = match maps.get(h.scenario.map.as_ref().map(|m| m.as_str())) {
+			Some(m) => m,
+			None => return Err(format!("unknown map {:?}", h.scenario.map))
+		};
+		let mut game = GameState::new(map, h.config.clone(), h.scenario.seed);
+		for &(client, side) in h.clients.iter() {
+			game.set_client_side(client, side);
+		}
+		for client in h.spectators.iter() {
+			game.spectate(*client);
+		}
+		Ok(game)
+	}
+}
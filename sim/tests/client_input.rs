@@ -0,0 +1,157 @@
+extern crate ds;
+extern crate sim;
+extern crate rand;
+
+mod common;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use sim::{ClientId, SoldierError};
+use sim::config::GameConfig;
+
+use crate::common::{errors, new_game};
+
+// seeded so that every run feeds the same inputs
+fn rng(seed: u32) -> XorShiftRng {
+	XorShiftRng::from_seed([seed, 0x9e37_79b9, 0x7f4a_7c15, 0x2545_f491])
+}
+
+// mostly near the valid range, sometimes anything
+fn soldier_id(rng: &mut XorShiftRng) -> ds::SoldierID {
+	match rng.gen_range(0, 4) {
+		0 => ds::SoldierID(rng.gen()),
+		1 => ds::SoldierID(*rng.choose(&[i32::min_value(), -1, ds::MAX_NUM_SOLDIERS, i32::max_value()]).unwrap()),
+		_ => ds::SoldierID(rng.gen_range(-4, ds::MAX_NUM_SOLDIERS + 4)),
+	}
+}
+
+fn coord(rng: &mut XorShiftRng) -> f64 {
+	match rng.gen_range(0, 8) {
+		0 => *rng.choose(&[std::f64::NAN, std::f64::INFINITY, std::f64::NEG_INFINITY, 1e300, -0.0]).unwrap(),
+		_ => rng.gen_range(-1000.0, 1000.0),
+	}
+}
+
+fn game_msg(rng: &mut XorShiftRng) -> ds::GameMsg {
+	match rng.gen_range(0, 9) {
+		0 => ds::GameMsg::TakeControl(soldier_id(rng)),
+		1 => ds::GameMsg::QueryStatus,
+		2 => ds::GameMsg::MoveTo(soldier_id(rng), ds::Position::new(coord(rng), coord(rng))),
+		3 => ds::GameMsg::ChooseSide(if rng.gen() { ds::Side::Red } else { ds::Side::Blue }),
+		4 => ds::GameMsg::Spectate,
+		5 => ds::GameMsg::ReleaseControl(soldier_id(rng)),
+		6 => ds::GameMsg::TransferControl(soldier_id(rng), ds::PlayerId(rng.gen_range(0, 5))),
+		7 => ds::GameMsg::ListPlayers,
+		_ => ds::GameMsg::Engage(soldier_id(rng), soldier_id(rng)),
+	}
+}
+
+#[test]
+fn soldier_ids_are_checked() {
+	let game = new_game();
+	let deployed = GameConfig::default().soldiers_per_side * 2;
+	assert_eq!(game.soldier_index(ds::SoldierID(0)), Ok(0));
+	assert_eq!(game.soldier_index(ds::SoldierID(deployed - 1)), Ok(deployed as usize - 1));
+	assert_eq!(game.soldier_index(ds::SoldierID(deployed)), Err(SoldierError::NotInGame(ds::SoldierID(deployed))));
+	for i in [-1, i32::min_value(), ds::MAX_NUM_SOLDIERS, i32::max_value()].iter() {
+		assert_eq!(game.soldier_index(ds::SoldierID(*i)), Err(SoldierError::OutOfRange(ds::SoldierID(*i))));
+	}
+}
+
+#[test]
+fn invalid_soldiers_get_an_error() {
+	let mut game = new_game();
+	game.take_messages();
+	let msgs = vec![
+		ds::GameMsg::TakeControl(ds::SoldierID(-1)),
+		ds::GameMsg::MoveTo(ds::SoldierID(ds::MAX_NUM_SOLDIERS), ds::Position::new(0.0, 0.0)),
+		ds::GameMsg::Engage(ds::SoldierID(i32::min_value()), ds::SoldierID(0)),
+	];
+	for msg in msgs {
+		game.game_msg(ClientId(0), msg);
+		assert_eq!(errors(&mut game), vec![ds::ErrorCode::InvalidSoldier]);
+	}
+	// a valid soldier engaging an invalid target
+	game.game_msg(ClientId(0), ds::GameMsg::TakeControl(ds::SoldierID(0)));
+	game.take_messages();
+	game.game_msg(ClientId(0), ds::GameMsg::Engage(ds::SoldierID(0), ds::SoldierID(-7)));
+	assert_eq!(errors(&mut game), vec![ds::ErrorCode::InvalidSoldier]);
+}
+
+#[test]
+fn only_enemies_can_be_engaged() {
+	let mut game = new_game();
+	game.game_msg(ClientId(0), ds::GameMsg::TakeControl(ds::SoldierID(0)));
+	game.take_messages();
+	game.game_msg(ClientId(0), ds::GameMsg::Engage(ds::SoldierID(0), ds::SoldierID(0)));
+	assert_eq!(errors(&mut game), vec![ds::ErrorCode::InvalidSoldier]);
+	game.game_msg(ClientId(0), ds::GameMsg::Engage(ds::SoldierID(0), ds::SoldierID(1)));
+	assert_eq!(errors(&mut game), vec![ds::ErrorCode::WrongSide]);
+	let blue = GameConfig::default().soldiers_per_side;
+	game.game_msg(ClientId(0), ds::GameMsg::Engage(ds::SoldierID(0), ds::SoldierID(blue)));
+	assert_eq!(errors(&mut game), vec![]);
+}
+
+#[test]
+fn non_finite_destinations_are_rejected() {
+	let mut game = new_game();
+	game.game_msg(ClientId(0), ds::GameMsg::TakeControl(ds::SoldierID(0)));
+	game.take_messages();
+	for &(x, y) in [(std::f64::NAN, 0.0), (0.0, std::f64::INFINITY), (std::f64::NEG_INFINITY, std::f64::NAN)].iter() {
+		game.game_msg(ClientId(0), ds::GameMsg::MoveTo(ds::SoldierID(0), ds::Position::new(x, y)));
+		assert_eq!(errors(&mut game), vec![ds::ErrorCode::InvalidPosition]);
+	}
+	let pos = game.full_state()[0].1.external.position;
+	game.tick();
+	assert_eq!(game.full_state()[0].1.external.position, pos);
+}
+
+#[test]
+fn destinations_outside_the_map_are_rejected() {
+	let mut game = new_game();
+	game.game_msg(ClientId(0), ds::GameMsg::TakeControl(ds::SoldierID(0)));
+	game.take_messages();
+	// the flat map reaches 500 meters from the center
+	for &(x, y) in [(1e300, 0.0), (0.0, -501.0), (-600.0, 600.0)].iter() {
+		game.game_msg(ClientId(0), ds::GameMsg::MoveTo(ds::SoldierID(0), ds::Position::new(x, y)));
+		assert_eq!(errors(&mut game), vec![ds::ErrorCode::InvalidPosition]);
+	}
+	game.game_msg(ClientId(0), ds::GameMsg::MoveTo(ds::SoldierID(0), ds::Position::new(-490.0, 0.0)));
+	assert_eq!(errors(&mut game), vec![]);
+}
+
+#[test]
+fn random_messages_do_not_panic() {
+	let mut rng = rng(1);
+	let mut game = new_game();
+	for i in 0..5000 {
+		let client = ClientId(rng.gen_range(0, 4));
+		let msg = game_msg(&mut rng);
+		game.game_msg(client, msg);
+		if i % 10 == 0 {
+			game.tick();
+		}
+		game.take_messages();
+	}
+}
+
+#[test]
+fn mutated_msgpack_does_not_panic() {
+	let mut rng = rng(2);
+	let mut game = new_game();
+	for i in 0..20000 {
+		let mut buf = ds::encode_game_msg(&game_msg(&mut rng));
+		for _ in 0..rng.gen_range(0, 3) {
+			let pos = rng.gen_range(0, buf.len());
+			buf[pos] = rng.gen();
+		}
+		match ds::decode_game_msg(&buf) {
+			Ok(msg) => game.game_msg(ClientId(rng.gen_range(0, 4)), msg),
+			Err(_) => ()
+		}
+		if i % 20 == 0 {
+			game.tick();
+		}
+		game.take_messages();
+	}
+}
@@ -0,0 +1,26 @@
+// helpers shared by the test crates, not all of them use every one
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use sim::{ClientId, GameState};
+use sim::config::GameConfig;
+
+// a game on a flat map, clients 0 and 2 play red and 1 blue
+pub fn new_game() -> GameState {
+	let mut game = GameState::new(Arc::new(sim::osm::flat_map()), GameConfig::default(), 1);
+	for c in 0..3 {
+		game.client_connected(ClientId(c));
+	}
+	game
+}
+
+// the errors sent since messages were last taken
+pub fn errors(game: &mut GameState) -> Vec<ds::ErrorCode> {
+	game.take_messages().into_iter()
+		.filter_map(|(_, m)| match m {
+			ds::ServerMsg::Error { code, .. } => Some(code),
+			_ => None
+		})
+		.collect()
+}
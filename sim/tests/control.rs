@@ -0,0 +1,120 @@
+extern crate ds;
+extern crate sim;
+
+mod common;
+
+use std::sync::Arc;
+
+use sim::{ClientId, GameState};
+use sim::config::GameConfig;
+
+use crate::common::errors;
+
+// client 0 controls soldier 0
+fn new_game() -> GameState {
+	let mut game = common::new_game();
+	game.game_msg(ClientId(0), ds::GameMsg::TakeControl(ds::SoldierID(0)));
+	game.take_messages();
+	game
+}
+
+#[test]
+fn transfer_notifies_both_players() {
+	let mut game = new_game();
+	game.game_msg(ClientId(0), ds::GameMsg::TransferControl(ds::SoldierID(0), ds::PlayerId(2)));
+	let msgs = game.take_messages();
+	assert!(msgs.iter().any(|(c, m)| match m {
+		ds::ServerMsg::ControlLost(ds::SoldierID(0)) => *c == ClientId(0),
+		_ => false
+	}));
+	assert!(msgs.iter().any(|(c, m)| match m {
+		ds::ServerMsg::YouNowHaveControl(ds::SoldierID(0), _) => *c == ClientId(2),
+		_ => false
+	}));
+
+	// the soldier is no longer the old player's to command
+	game.game_msg(ClientId(0), ds::GameMsg::MoveTo(ds::SoldierID(0), ds::Position::new(1.0, 1.0)));
+	assert_eq!(errors(&mut game), vec![ds::ErrorCode::NotController]);
+}
+
+#[test]
+fn transfer_needs_a_teammate() {
+	let mut game = new_game();
+	game.game_msg(ClientId(0), ds::GameMsg::TransferControl(ds::SoldierID(0), ds::PlayerId(1)));
+	assert_eq!(errors(&mut game), vec![ds::ErrorCode::WrongSide]);
+	game.game_msg(ClientId(0), ds::GameMsg::TransferControl(ds::SoldierID(0), ds::PlayerId(9)));
+	assert_eq!(errors(&mut game), vec![ds::ErrorCode::NoSuchPlayer]);
+	game.game_msg(ClientId(2), ds::GameMsg::TransferControl(ds::SoldierID(0), ds::PlayerId(2)));
+	assert_eq!(errors(&mut game), vec![ds::ErrorCode::NotController]);
+}
+
+#[test]
+fn released_soldiers_become_available() {
+	let mut game = new_game();
+	assert!(!game.available_soldiers(ClientId(2)).contains(&ds::SoldierID(0)));
+	game.game_msg(ClientId(0), ds::GameMsg::ReleaseControl(ds::SoldierID(0)));
+	let msgs = game.take_messages();
+	assert!(msgs.iter().any(|(c, m)| match m {
+		ds::ServerMsg::ControlLost(ds::SoldierID(0)) => *c == ClientId(0),
+		_ => false
+	}));
+	assert!(msgs.iter().any(|(c, m)| match m {
+		ds::ServerMsg::AvailableSoldiers(s) => *c == ClientId(2) && s.contains(&ds::SoldierID(0)),
+		_ => false
+	}));
+	// the blue player isn't offered red soldiers
+	assert!(!msgs.iter().any(|(c, _)| *c == ClientId(1)));
+}
+
+#[test]
+fn spectators_choose_a_side_in_the_next_game() {
+	let mut game = new_game();
+	game.game_msg(ClientId(0), ds::GameMsg::Spectate);
+	assert!(game.take_messages().iter().any(|(_, m)| match m {
+		ds::ServerMsg::FullState(_) => true,
+		_ => false
+	}));
+	assert_eq!(game.spectators(), vec![ClientId(0)]);
+	game.game_msg(ClientId(0), ds::GameMsg::ChooseSide(ds::Side::Blue));
+	assert_eq!(errors(&mut game), vec![ds::ErrorCode::NotAllowed]);
+	assert_eq!(game.client_side(ClientId(0)), None);
+	game.restart(Arc::new(sim::osm::flat_map()), GameConfig::default(), 2);
+	assert_eq!(game.spectators(), vec![]);
+	game.game_msg(ClientId(0), ds::GameMsg::ChooseSide(ds::Side::Blue));
+	assert_eq!(errors(&mut game), vec![]);
+	assert_eq!(game.client_side(ClientId(0)), Some(ds::Side::Blue));
+}
+
+#[test]
+fn spectating_releases_soldiers_to_teammates() {
+	let mut game = new_game();
+	game.game_msg(ClientId(0), ds::GameMsg::Spectate);
+	let msgs = game.take_messages();
+	assert!(msgs.iter().any(|(c, m)| match m {
+		ds::ServerMsg::ControlLost(ds::SoldierID(0)) => *c == ClientId(0),
+		_ => false
+	}));
+	assert!(msgs.iter().any(|(c, m)| match m {
+		ds::ServerMsg::AvailableSoldiers(s) => *c == ClientId(2) && s.contains(&ds::SoldierID(0)),
+		_ => false
+	}));
+}
+
+#[test]
+fn spectators_coming_back_still_spectate() {
+	let mut game = new_game();
+	game.game_msg(ClientId(0), ds::GameMsg::Spectate);
+	game.client_disconnected(ClientId(0));
+	game.client_connected(ClientId(0));
+	assert_eq!(game.client_side(ClientId(0)), None);
+	assert_eq!(game.spectators(), vec![ClientId(0)]);
+	let msgs = game.take_messages();
+	assert!(!msgs.iter().any(|(_, m)| match m {
+		ds::ServerMsg::SideAssigned(_) => true,
+		_ => false
+	}));
+	assert!(msgs.iter().any(|(c, m)| match m {
+		ds::ServerMsg::FullState(_) => *c == ClientId(0),
+		_ => false
+	}));
+}
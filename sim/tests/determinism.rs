@@ -0,0 +1,44 @@
+extern crate ds;
+extern crate sim;
+
+use std::sync::Arc;
+
+use sim::{ClientId, GameState};
+use sim::config::GameConfig;
+
+// both sides walk to the center and fight, returns the checksum after
+// every tick
+fn play(seed: u64) -> Vec<u64> {
+	let mut game = GameState::new(Arc::new(sim::osm::flat_map()), GameConfig::default(), seed);
+	let n = GameConfig::default().soldiers_per_side;
+	game.client_connected(ClientId(0));
+	game.client_connected(ClientId(1));
+	for i in 0..n {
+		let (red, blue) = (ds::SoldierID(i), ds::SoldierID(n + i));
+		game.game_msg(ClientId(0), ds::GameMsg::TakeControl(red));
+		game.game_msg(ClientId(1), ds::GameMsg::TakeControl(blue));
+		game.game_msg(ClientId(0), ds::GameMsg::MoveTo(red, ds::Position::new(-5.0, i as f64 * 10.0)));
+		game.game_msg(ClientId(1), ds::GameMsg::MoveTo(blue, ds::Position::new(5.0, i as f64 * 10.0)));
+		game.game_msg(ClientId(0), ds::GameMsg::Engage(red, blue));
+		game.game_msg(ClientId(1), ds::GameMsg::Engage(blue, red));
+	}
+	let mut sums = vec![];
+	for _ in 0..3000 {
+		game.tick();
+		game.take_messages();
+		sums.push(game.checksum());
+	}
+	// the random hit rolls were made
+	assert!(game.full_state().iter().any(|(_, s)| s.internal.health < 100));
+	sums
+}
+
+#[test]
+fn same_seed_and_input_give_the_same_game() {
+	assert_eq!(play(7), play(7));
+}
+
+#[test]
+fn seed_changes_the_game() {
+	assert_ne!(play(7).last(), play(8).last());
+}
@@ -0,0 +1,162 @@
+extern crate ds;
+extern crate sim;
+extern crate serde;
+extern crate rmp_serde;
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::sync::Arc;
+
+use serde::Serialize;
+use rmp_serde::Serializer;
+
+use sim::{ClientId, GameState};
+use sim::config::{GameConfig, MapConfig};
+use sim::replay::{ClientEvent, Recorder, Replay, ReplayHeader};
+
+mod common;
+
+use crate::common::errors;
+
+const TICKS: u64 = 1450;
+
+// both clients connect, take their soldiers over a few ticks and send them
+// towards each other, firing
+fn events() -> VecDeque<(u64, ClientId, ClientEvent)> {
+	let mut events = VecDeque::new();
+	events.push_back((0, ClientId(0), ClientEvent::Connected));
+	events.push_back((0, ClientId(1), ClientEvent::Connected));
+	for i in 0..4 {
+		let (red, blue) = (ds::SoldierID(i), ds::SoldierID(4 + i));
+		let t = 10 * i as u64;
+		events.push_back((t, ClientId(0), ClientEvent::Msg(ds::GameMsg::TakeControl(red))));
+		events.push_back((t, ClientId(1), ClientEvent::Msg(ds::GameMsg::TakeControl(blue))));
+		events.push_back((t + 5, ClientId(0), ClientEvent::Msg(ds::GameMsg::MoveTo(red, ds::Position::new(-5.0, i as f64 * 10.0)))));
+		events.push_back((t + 5, ClientId(1), ClientEvent::Msg(ds::GameMsg::MoveTo(blue, ds::Position::new(5.0, i as f64 * 10.0)))));
+		events.push_back((t + 5, ClientId(0), ClientEvent::Msg(ds::GameMsg::Engage(red, blue))));
+		events.push_back((t + 5, ClientId(1), ClientEvent::Msg(ds::GameMsg::Engage(blue, red))));
+	}
+	events
+}
+
+// records a small fight to a replay file in the temporary directory
+fn record(room: &str) -> (String, GameState) {
+	let scenario = ds::Scenario { seed: 3, soldiers_per_side: 4, map: None };
+	let mut game = GameState::new(Arc::new(sim::osm::flat_map()), GameConfig::default(), scenario.seed);
+	let header = ReplayHeader {
+		config: GameConfig::default(),
+		scenario: scenario,
+		clients: game.clients(),
+		spectators: game.spectators(),
+	};
+	let dir = std::env::temp_dir();
+	let mut rec = Recorder::create(dir.to_str().unwrap(), room, &header).unwrap();
+	let start = game.full_state();
+	let mut events = events();
+	for _ in 0..TICKS {
+		while events.front().map_or(false, |e| e.0 == game.ticks()) {
+			let (tick, client, ev) = events.pop_front().unwrap();
+			rec.record(tick, client, ev.clone()).unwrap();
+			ev.apply(client, &mut game);
+		}
+		assert_eq!(errors(&mut game), vec![]);
+		game.tick();
+		rec.ticked(&game).unwrap();
+	}
+	assert!(events.is_empty());
+	// the soldiers moved and fought, so there's something to check
+	let end = game.full_state();
+	assert!(end.iter().any(|(_, s)| s.internal.health < 100));
+	assert!(start.iter().zip(end.iter()).all(|((_, a), (_, b))| a.external.position != b.external.position));
+	let path = rec.finish(&game).unwrap().unwrap();
+	(path, game)
+}
+
+// plays the replay back, returns the game and whether every checkpoint matched
+fn play(replay: &Replay) -> (GameState, bool) {
+	let maps = sim::osm::load_maps(&MapConfig::default(), &HashMap::new()).unwrap();
+	let mut game = replay.start(&maps).unwrap();
+	let mut events = replay.events.iter().peekable();
+	let mut checkpoints = replay.checkpoints.iter().peekable();
+	let mut matched = true;
+	loop {
+		while let Some(&&(ticks, checksum)) = checkpoints.peek() {
+			if ticks > game.ticks() {
+				break;
+			}
+			matched = matched && checksum == game.checksum();
+			checkpoints.next();
+		}
+		while let Some(ev) = events.peek() {
+			if ev.tick > game.ticks() {
+				break;
+			}
+			ev.event.clone().apply(ev.client, &mut game);
+			events.next();
+		}
+		if game.ticks() >= replay.ticks() {
+			return (game, matched);
+		}
+		game.tick();
+	}
+}
+
+#[test]
+fn replays_play_out_like_the_recorded_game() {
+	let (path, recorded) = record("replay test");
+	let replay = Replay::load(&path).unwrap();
+	fs::remove_file(&path).unwrap();
+	assert_eq!(replay.ticks(), TICKS);
+	assert_eq!(replay.checkpoints.len() as u64, TICKS / 100 + 1);
+	let (game, matched) = play(&replay);
+	assert!(matched);
+	assert_eq!(game.checksum(), recorded.checksum());
+	assert_eq!(format!("{:?}", game.full_state()), format!("{:?}", recorded.full_state()));
+}
+
+#[test]
+fn cut_replays_are_read_up_to_the_cut() {
+	let (path, _) = record("cut replay test");
+	let mut buf = fs::read(&path).unwrap();
+	let len = buf.len();
+	buf.truncate(len - 3);
+	fs::write(&path, buf).unwrap();
+	let replay = Replay::load(&path);
+	fs::remove_file(&path).unwrap();
+	let replay = replay.unwrap();
+	// the final checkpoint was cut off
+	assert_eq!(replay.checkpoints.last().map(|c| c.0), Some(TICKS / 100 * 100));
+	assert!(play(&replay).1);
+}
+
+#[test]
+fn replays_of_other_protocol_versions_are_refused() {
+	let path = std::env::temp_dir().join("old-version.replay");
+	let mut buf = Vec::new();
+	(ds::PROTOCOL_VERSION - 1).serialize(&mut Serializer::new(&mut buf)).unwrap();
+	fs::write(&path, buf).unwrap();
+	let res = Replay::load(path.to_str().unwrap());
+	fs::remove_file(&path).unwrap();
+	assert!(res.unwrap_err().contains("protocol version"));
+}
+
+#[test]
+fn replays_started_together_get_their_own_files() {
+	let header = ReplayHeader {
+		config: GameConfig::default(),
+		scenario: ds::Scenario { seed: 5, soldiers_per_side: 4, map: None },
+		clients: vec![],
+		spectators: vec![],
+	};
+	let dir = std::env::temp_dir();
+	let mut game = GameState::new(Arc::new(sim::osm::flat_map()), GameConfig::default(), 5);
+	let first = Recorder::create(dir.to_str().unwrap(), "restart test", &header).unwrap();
+	let second = Recorder::create(dir.to_str().unwrap(), "restart test", &header).unwrap();
+	// the restarted game ends before it starts, which removes only its own file
+	assert_eq!(second.finish(&game).unwrap(), None);
+	game.tick();
+	let path = first.finish(&game).unwrap().unwrap();
+	let replay = Replay::load(&path);
+	fs::remove_file(&path).unwrap();
+	assert_eq!(replay.unwrap().ticks(), 1);
+}
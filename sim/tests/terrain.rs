@@ -0,0 +1,103 @@
+extern crate ds;
+extern crate geo;
+extern crate sim;
+
+mod common;
+
+use std::sync::Arc;
+
+use geo::{Coordinate, LineString, MultiPolygon, Polygon};
+
+use sim::{ClientId, GameState};
+use sim::config::GameConfig;
+use sim::osm::{MapData, Terrain, TerrainKind};
+
+use crate::common::errors;
+
+fn rectangle(kind: TerrainKind, x0: f64, y0: f64, x1: f64, y1: f64) -> Terrain {
+	let ring: LineString<f64> = [(x0, y0), (x1, y0), (x1, y1), (x0, y1), (x0, y0)].iter()
+		.map(|&(x, y)| Coordinate { x: x, y: y })
+		.collect();
+	Terrain::new(kind, MultiPolygon(vec![Polygon::new(ring, vec![])]))
+}
+
+fn building(x0: f64, y0: f64, x1: f64, y1: f64) -> Terrain {
+	rectangle(TerrainKind::Building, x0, y0, x1, y1)
+}
+
+// the flat map with one building on the red deployment zone and one
+// off the roads
+fn built_up_map() -> MapData {
+	let mut map = sim::osm::flat_map();
+	map.terrain.push(building(-220.0, -30.0, -180.0, 30.0));
+	map.terrain.push(building(100.0, 100.0, 200.0, 200.0));
+	map
+}
+
+// the flat map with a river across it north of the center. The road
+// north from the center crosses it on a bridge.
+fn river_map() -> MapData {
+	let mut map = sim::osm::flat_map();
+	map.terrain.push(rectangle(TerrainKind::Water, -500.0, 60.0, 500.0, 100.0));
+	map
+}
+
+fn positions(game: &GameState) -> Vec<ds::Position> {
+	game.full_state().into_iter().map(|(_, s)| s.external.position).collect()
+}
+
+// sends soldier 0 to the goal and checks that it gets there on walkable ground
+fn walk(game: &mut GameState, map: &MapData, goal: ds::Position) {
+	game.game_msg(ClientId(0), ds::GameMsg::MoveTo(ds::SoldierID(0), goal));
+	assert_eq!(errors(game), vec![]);
+	for _ in 0..10000 {
+		game.tick();
+		game.take_messages();
+		assert!(map.walkable(&positions(game)[0]), "soldier walked to {:?}", positions(game)[0]);
+	}
+	assert!(positions(game)[0].dist(&goal) < 1.0, "soldier stopped at {:?}", positions(game)[0]);
+}
+
+#[test]
+fn soldiers_are_deployed_on_walkable_ground() {
+	let map = Arc::new(built_up_map());
+	let game = GameState::new(map.clone(), GameConfig::default(), 1);
+	for p in positions(&game) {
+		assert!(map.walkable(&p), "soldier deployed at {:?}", p);
+	}
+}
+
+#[test]
+fn soldiers_walk_around_buildings() {
+	let map = Arc::new(built_up_map());
+	let mut game = GameState::new(map.clone(), GameConfig::default(), 1);
+	game.client_connected(ClientId(0));
+	game.game_msg(ClientId(0), ds::GameMsg::TakeControl(ds::SoldierID(0)));
+	// the straight line there goes through the building
+	walk(&mut game, &map, ds::Position::new(250.0, 250.0));
+}
+
+#[test]
+fn soldiers_cross_rivers_on_bridges() {
+	let map = Arc::new(river_map());
+	let mut game = GameState::new(map.clone(), GameConfig::default(), 1);
+	game.client_connected(ClientId(0));
+	game.game_msg(ClientId(0), ds::GameMsg::TakeControl(ds::SoldierID(0)));
+	// onto the bridge, the straight line there goes through the river
+	walk(&mut game, &map, ds::Position::new(0.0, 80.0));
+	// and off it on the other bank
+	walk(&mut game, &map, ds::Position::new(-100.0, 150.0));
+	// swimming isn't a route
+	game.game_msg(ClientId(0), ds::GameMsg::MoveTo(ds::SoldierID(0), ds::Position::new(-100.0, 80.0)));
+	assert_eq!(errors(&mut game), vec![ds::ErrorCode::InvalidPosition]);
+}
+
+#[test]
+fn unwalkable_destinations_are_rejected() {
+	let mut game = GameState::new(Arc::new(built_up_map()), GameConfig::default(), 1);
+	game.client_connected(ClientId(0));
+	game.game_msg(ClientId(0), ds::GameMsg::TakeControl(ds::SoldierID(0)));
+	game.take_messages();
+	game.game_msg(ClientId(0), ds::GameMsg::MoveTo(ds::SoldierID(0), ds::Position::new(150.0, 150.0)));
+	assert_eq!(errors(&mut game), vec![ds::ErrorCode::InvalidPosition]);
+}